@@ -1,286 +1,1047 @@
-use crate::event::AuthRequest;
+use crate::candle::{Candle, Interval};
+use crate::decode::{DecodeError, decode_response};
+use crate::error_code::BitvavoErrorCode;
+use crate::event::{
+    Account, AccountTrade, Asset, AuthRequest, Balance, BitvavoEvent, Deposit, DepositAddress,
+    Order, Ticker24h, TickerPrice, Withdrawal,
+};
+use crate::market::Market;
+use crate::order::{
+    OrderRequest, OrderRequestError, OrderType, OrderUpdateRequest, OrdersFilter,
+    SelfTradePrevention, TimeInForce, TriggerReference, TriggerType,
+};
+use crate::price_level::Book;
 use crate::rug_float_serde::FloatWrapper;
-use futures_util::stream::SplitSink;
-use futures_util::SinkExt;
+use crate::side::Side;
+use crate::subscription::{Channel, Subscription, SubscriptionError, SubscriptionRegistry};
+use crate::trade::Trade;
+use futures_util::stream::{SplitSink, SplitStream};
+use futures_util::{SinkExt, StreamExt};
 use serde_json::json;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt::{Debug, Formatter};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
 use tokio::net::TcpStream;
+use tokio::sync::{Mutex, mpsc, oneshot};
 use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
 use tungstenite::Bytes;
 
-#[derive(Debug, Default)]
+pub type WriteStream = SplitSink<WebSocketStream<MaybeTlsStream<TcpStream>>, tungstenite::Message>;
+pub type ReadStream = SplitStream<WebSocketStream<MaybeTlsStream<TcpStream>>>;
+
+// everything that is not a response to an action (subscription updates, authentication
+// and subscription confirmations, undecodable messages) ends up here
+pub type EventReceiver = mpsc::UnboundedReceiver<Result<BitvavoEvent, DecodeError>>;
+
+// how long an action waits for its response unless set with Bitvavo::with_timeout
+pub const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+// the actions waiting for a response; once the reader is gone nothing could
+// complete them, so no more are taken
+#[derive(Default)]
+struct Requests {
+    waiting: HashMap<u64, oneshot::Sender<Result<BitvavoEvent, DecodeError>>>,
+    closed: bool,
+}
+
+type Pending = Arc<std::sync::Mutex<Requests>>;
+
+pub enum BitvavoError {
+    Transport(tungstenite::Error),
+    Decode(DecodeError),
+    ConnectionClosed,
+    // no response within the request timeout
+    Timeout,
+    Exchange {
+        error_code: BitvavoErrorCode,
+        error: String,
+    },
+    UnexpectedResponse(Box<BitvavoEvent>),
+    // rejected before sending
+    InvalidOrder(OrderRequestError),
+}
+
+impl Debug for BitvavoError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BitvavoError::Transport(e) => write!(f, "Transport({:?})", e),
+            BitvavoError::Decode(e) => write!(f, "Decode({:?})", e),
+            BitvavoError::ConnectionClosed => write!(f, "ConnectionClosed"),
+            BitvavoError::Timeout => write!(f, "Timeout"),
+            BitvavoError::Exchange { error_code, error } => {
+                write!(f, "Exchange({}: {})", error_code, error)
+            }
+            BitvavoError::UnexpectedResponse(e) => write!(f, "UnexpectedResponse({:?})", e),
+            BitvavoError::InvalidOrder(e) => write!(f, "InvalidOrder({})", e),
+        }
+    }
+}
+
+impl From<tungstenite::Error> for BitvavoError {
+    fn from(value: tungstenite::Error) -> Self {
+        BitvavoError::Transport(value)
+    }
+}
+
+impl From<DecodeError> for BitvavoError {
+    fn from(value: DecodeError) -> Self {
+        BitvavoError::Decode(value)
+    }
+}
+
+impl From<OrderRequestError> for BitvavoError {
+    fn from(value: OrderRequestError) -> Self {
+        BitvavoError::InvalidOrder(value)
+    }
+}
+
+// channels enabled without explicit markets apply to every market given through
+// `with_market`/`with_markets`
+#[derive(Debug, Default, Clone)]
 pub struct SubscriptionBuilder {
-    market: String,
-    trades: bool,
-    account: bool,
-    book: bool,
-    ticker: bool,
-    candles: bool,
-    candles_interval: &'static str,
+    markets: Vec<String>,
+    channels: Vec<(Channel, Vec<String>)>,
 }
 
 impl SubscriptionBuilder {
     pub fn with_market(mut self, market: String) -> Self {
-        self.market = market;
+        self.markets.push(market);
+        self
+    }
+
+    pub fn with_markets(mut self, markets: impl IntoIterator<Item = String>) -> Self {
+        self.markets.extend(markets);
+        self
+    }
+
+    // a market looked up in a MarketRegistry, so it is known to exist
+    pub fn with_known_market(self, market: &Market) -> Self {
+        self.with_market(market.market().to_string())
+    }
+
+    pub fn with_known_markets<'a>(self, markets: impl IntoIterator<Item = &'a Market>) -> Self {
+        self.with_markets(markets.into_iter().map(|m| m.market().to_string()))
+    }
+
+    pub fn with_trades(self) -> Self {
+        self.with_channel(Channel::Trades)
+    }
+
+    pub fn with_account(self) -> Self {
+        self.with_channel(Channel::Account)
+    }
+
+    pub fn with_book(self) -> Self {
+        self.with_channel(Channel::Book)
+    }
+
+    pub fn with_ticker(self) -> Self {
+        self.with_channel(Channel::Ticker)
+    }
+
+    // can be called several times, once per interval
+    pub fn with_candles(self, interval: Interval) -> Self {
+        self.with_channel(Channel::Candles(interval))
+    }
+
+    pub fn with_channel(mut self, channel: Channel) -> Self {
+        self.channels.push((channel, Vec::new()));
+        self
+    }
+
+    pub fn with_channel_for(
+        mut self,
+        channel: Channel,
+        markets: impl IntoIterator<Item = String>,
+    ) -> Self {
+        self.channels.push((channel, markets.into_iter().collect()));
+        self
+    }
+
+    pub fn build(self) -> Result<Subscription, SubscriptionError> {
+        let mut channels = BTreeMap::<Channel, BTreeSet<String>>::new();
+        for (channel, markets) in self.channels {
+            let markets = if markets.is_empty() {
+                &self.markets
+            } else {
+                &markets
+            };
+            channels
+                .entry(channel)
+                .or_default()
+                .extend(markets.iter().cloned());
+        }
+        Subscription::new(channels)
+    }
+}
+
+// market, side and order type are mandatory, the rest depends on the order type;
+// build() rejects what the exchange would reject
+#[derive(Debug, Default, Clone)]
+pub struct OrderRequestBuilder {
+    market: Option<String>,
+    side: Option<Side>,
+    order_type: Option<OrderType>,
+    amount: Option<FloatWrapper>,
+    amount_quote: Option<FloatWrapper>,
+    price: Option<FloatWrapper>,
+    time_in_force: Option<TimeInForce>,
+    post_only: bool,
+    self_trade_prevention: Option<SelfTradePrevention>,
+    client_order_id: Option<String>,
+    disable_market_protection: bool,
+    trigger: Option<(FloatWrapper, TriggerReference)>,
+}
+
+impl OrderRequestBuilder {
+    pub fn with_market(mut self, market: String) -> Self {
+        self.market = Some(market);
+        self
+    }
+
+    pub fn with_known_market(self, market: &Market) -> Self {
+        self.with_market(market.market().to_string())
+    }
+
+    pub fn with_side(mut self, side: Side) -> Self {
+        self.side = Some(side);
+        self
+    }
+
+    pub fn with_order_type(mut self, order_type: OrderType) -> Self {
+        self.order_type = Some(order_type);
+        self
+    }
+
+    // in the base currency
+    pub fn with_amount(mut self, amount: FloatWrapper) -> Self {
+        self.amount = Some(amount);
+        self
+    }
+
+    // in the quote currency, market, stopLoss and takeProfit orders only
+    pub fn with_amount_quote(mut self, amount_quote: FloatWrapper) -> Self {
+        self.amount_quote = Some(amount_quote);
+        self
+    }
+
+    pub fn with_price(mut self, price: FloatWrapper) -> Self {
+        self.price = Some(price);
+        self
+    }
+
+    pub fn with_time_in_force(mut self, time_in_force: TimeInForce) -> Self {
+        self.time_in_force = Some(time_in_force);
+        self
+    }
+
+    pub fn with_post_only(mut self) -> Self {
+        self.post_only = true;
+        self
+    }
+
+    pub fn with_self_trade_prevention(
+        mut self,
+        self_trade_prevention: SelfTradePrevention,
+    ) -> Self {
+        self.self_trade_prevention = Some(self_trade_prevention);
+        self
+    }
+
+    pub fn with_client_order_id(mut self, client_order_id: String) -> Self {
+        self.client_order_id = Some(client_order_id);
+        self
+    }
+
+    pub fn with_disabled_market_protection(mut self) -> Self {
+        self.disable_market_protection = true;
+        self
+    }
+
+    // the price at which a stop loss or take profit order triggers
+    pub fn with_trigger(mut self, amount: FloatWrapper, reference: TriggerReference) -> Self {
+        self.trigger = Some((amount, reference));
+        self
+    }
+
+    pub fn build(self) -> Result<OrderRequest, OrderRequestError> {
+        let (trigger_amount, trigger_reference) = self.trigger.unzip();
+        OrderRequest {
+            market: self.market.ok_or(OrderRequestError::MissingMarket)?,
+            side: self.side.ok_or(OrderRequestError::MissingSide)?,
+            order_type: self.order_type.ok_or(OrderRequestError::MissingOrderType)?,
+            amount: self.amount,
+            amount_quote: self.amount_quote,
+            price: self.price,
+            time_in_force: self.time_in_force,
+            post_only: self.post_only,
+            self_trade_prevention: self.self_trade_prevention,
+            client_order_id: self.client_order_id,
+            disable_market_protection: self.disable_market_protection,
+            trigger_type: trigger_amount.as_ref().map(|_| TriggerType::Price),
+            trigger_amount,
+            trigger_reference,
+        }
+        .validate()
+    }
+}
+
+// amends an open order in place, at least one of the with_* besides market and
+// order id is needed
+#[derive(Debug, Default, Clone)]
+pub struct OrderUpdateBuilder {
+    market: Option<String>,
+    order_id: Option<String>,
+    amount: Option<FloatWrapper>,
+    amount_remaining: Option<FloatWrapper>,
+    price: Option<FloatWrapper>,
+    trigger_amount: Option<FloatWrapper>,
+    time_in_force: Option<TimeInForce>,
+    self_trade_prevention: Option<SelfTradePrevention>,
+    post_only: Option<bool>,
+}
+
+impl OrderUpdateBuilder {
+    pub fn with_market(mut self, market: String) -> Self {
+        self.market = Some(market);
+        self
+    }
+
+    pub fn with_order_id(mut self, order_id: String) -> Self {
+        self.order_id = Some(order_id);
+        self
+    }
+
+    // the new total amount, including what has been filled already
+    pub fn with_amount(mut self, amount: FloatWrapper) -> Self {
+        self.amount = Some(amount);
+        self
+    }
+
+    pub fn with_amount_remaining(mut self, amount_remaining: FloatWrapper) -> Self {
+        self.amount_remaining = Some(amount_remaining);
+        self
+    }
+
+    pub fn with_price(mut self, price: FloatWrapper) -> Self {
+        self.price = Some(price);
         self
     }
 
-    pub fn with_trades(mut self) -> Self {
-        self.trades = true;
+    pub fn with_trigger_amount(mut self, trigger_amount: FloatWrapper) -> Self {
+        self.trigger_amount = Some(trigger_amount);
         self
     }
 
-    pub fn with_account(mut self) -> Self {
-        self.account = true;
+    pub fn with_time_in_force(mut self, time_in_force: TimeInForce) -> Self {
+        self.time_in_force = Some(time_in_force);
         self
     }
 
-    pub fn with_book(mut self) -> Self {
-        self.book = true;
+    pub fn with_self_trade_prevention(
+        mut self,
+        self_trade_prevention: SelfTradePrevention,
+    ) -> Self {
+        self.self_trade_prevention = Some(self_trade_prevention);
         self
     }
 
-    pub fn with_ticker(mut self) -> Self {
-        self.ticker = true;
+    pub fn with_post_only(mut self, post_only: bool) -> Self {
+        self.post_only = Some(post_only);
         self
     }
 
-    pub fn with_candles(mut self, interval: &'static str) -> Self {
-        self.candles = true;
-        self.candles_interval = interval;
+    pub fn build(self) -> Result<OrderUpdateRequest, OrderRequestError> {
+        OrderUpdateRequest {
+            market: self.market.ok_or(OrderRequestError::MissingMarket)?,
+            order_id: self.order_id.ok_or(OrderRequestError::MissingOrderId)?,
+            amount: self.amount,
+            amount_remaining: self.amount_remaining,
+            price: self.price,
+            trigger_amount: self.trigger_amount,
+            time_in_force: self.time_in_force,
+            self_trade_prevention: self.self_trade_prevention,
+            post_only: self.post_only,
+        }
+        .validate()
+    }
+}
+
+// pagination for the historical actions: at most `limit` entries between `start`
+// and `end`, both in milliseconds since the epoch, most recent first
+#[derive(Debug, Default, Clone)]
+pub struct RangeFilter {
+    limit: Option<u32>,
+    start: Option<u64>,
+    end: Option<u64>,
+    trade_ids: Option<(String, String)>,
+}
+
+impl RangeFilter {
+    pub fn with_limit(mut self, limit: u32) -> Self {
+        self.limit = Some(limit);
         self
     }
+
+    pub fn with_start(mut self, start: u64) -> Self {
+        self.start = Some(start);
+        self
+    }
+
+    pub fn with_end(mut self, end: u64) -> Self {
+        self.end = Some(end);
+        self
+    }
+
+    // getTrades and privateGetTrades only
+    pub fn with_trade_ids(mut self, from: String, to: String) -> Self {
+        self.trade_ids = Some((from, to));
+        self
+    }
+
+    fn apply(&self, message: &mut serde_json::Value) {
+        if let Some(limit) = self.limit {
+            message["limit"] = json!(limit);
+        }
+        if let Some(start) = self.start {
+            message["start"] = json!(start);
+        }
+        if let Some(end) = self.end {
+            message["end"] = json!(end);
+        }
+        if let Some((from, to)) = &self.trade_ids {
+            message["tradeIdFrom"] = json!(from);
+            message["tradeIdTo"] = json!(to);
+        }
+    }
 }
 
+#[derive(Clone)]
 pub struct Bitvavo {
-    stream: SplitSink<WebSocketStream<MaybeTlsStream<TcpStream>>, tungstenite::Message>,
+    stream: Arc<Mutex<WriteStream>>,
+    pending: Pending,
+    request_id: Arc<AtomicU64>,
+    subscriptions: Arc<std::sync::Mutex<SubscriptionRegistry>>,
+    timeout: Duration,
 }
 
 impl Bitvavo {
-    pub fn wrap(
-        write_stream: SplitSink<WebSocketStream<MaybeTlsStream<TcpStream>>, tungstenite::Message>,
-    ) -> Self {
-        Bitvavo {
-            stream: write_stream,
+    // takes both halves of the socket: the read half is moved into a reader task
+    // which completes the pending actions and forwards everything else to the
+    // returned receiver
+    pub fn wrap(write_stream: WriteStream, read_stream: ReadStream) -> (Self, EventReceiver) {
+        let bitvavo = Bitvavo {
+            stream: Arc::new(Mutex::new(write_stream)),
+            pending: Arc::default(),
+            request_id: Arc::new(AtomicU64::new(1)),
+            subscriptions: Arc::default(),
+            timeout: DEFAULT_REQUEST_TIMEOUT,
+        };
+        let (events_tx, events_rx) = mpsc::unbounded_channel();
+
+        tokio::spawn(read_loop(
+            read_stream,
+            Arc::clone(&bitvavo.stream),
+            Arc::clone(&bitvavo.pending),
+            events_tx,
+        ));
+
+        (bitvavo, events_rx)
+    }
+
+    // how long the actions wait for their response
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    async fn send(&self, message: tungstenite::Message) -> Result<(), tungstenite::Error> {
+        self.stream.lock().await.send(message).await
+    }
+
+    // sends a close frame; the reader stops once the exchange confirms it
+    pub async fn close(&self) -> Result<(), tungstenite::Error> {
+        self.stream.lock().await.close().await
+    }
+
+    // sends an action this client has no method for, without waiting for a
+    // response; whatever comes back arrives as an event
+    pub async fn send_action(&self, action: serde_json::Value) -> Result<(), tungstenite::Error> {
+        self.send(tungstenite::Message::Text(action.to_string().into()))
+            .await
+    }
+
+    async fn request(&self, mut message: serde_json::Value) -> Result<BitvavoEvent, BitvavoError> {
+        let request_id = self.request_id.fetch_add(1, Ordering::Relaxed);
+        message["requestId"] = json!(request_id);
+
+        let (tx, rx) = oneshot::channel();
+        {
+            let mut pending = self.pending.lock().unwrap();
+            if pending.closed {
+                return Err(BitvavoError::ConnectionClosed);
+            }
+            pending.waiting.insert(request_id, tx);
+        }
+
+        let sent = self
+            .send(tungstenite::Message::Text(message.to_string().into()))
+            .await;
+        if let Err(e) = sent {
+            self.pending.lock().unwrap().waiting.remove(&request_id);
+            return Err(e.into());
+        }
+
+        match tokio::time::timeout(self.timeout, rx).await {
+            Ok(Ok(Ok(BitvavoEvent::Error {
+                error_code, error, ..
+            }))) => Err(BitvavoError::Exchange { error_code, error }),
+            Ok(Ok(decoded)) => Ok(decoded?),
+            Ok(Err(_)) => Err(BitvavoError::ConnectionClosed),
+            Err(_) => {
+                self.pending.lock().unwrap().waiting.remove(&request_id);
+                Err(BitvavoError::Timeout)
+            }
         }
     }
 
-    pub async fn authenticate(&mut self, auth_req: AuthRequest) -> Result<(), tungstenite::Error> {
+    pub async fn authenticate(&self, auth_req: AuthRequest) -> Result<(), tungstenite::Error> {
         let auth_req_str = serde_json::to_string(&auth_req).unwrap();
-        self.stream
-            .send(tungstenite::Message::Text(auth_req_str.into()))
+        self.send(tungstenite::Message::Text(auth_req_str.into()))
             .await
     }
 
-    pub async fn get_book(&mut self, market: &str) -> Result<(), tungstenite::Error> {
+    pub async fn get_book(&self, market: &str) -> Result<Book, BitvavoError> {
         let markets_message = json!({
             "action": "getBook",
             "market": market,
         });
-        self.stream
-            .send(tungstenite::Message::Text(
-                markets_message.to_string().into(),
-            ))
-            .await
+        match self.request(markets_message).await? {
+            BitvavoEvent::BookSnapshot(book) => Ok(book),
+            other => Err(BitvavoError::UnexpectedResponse(Box::new(other))),
+        }
     }
 
-    pub async fn subscribe(
-        &mut self,
-        subscribe_builder: SubscriptionBuilder,
-    ) -> Result<(), tungstenite::Error> {
-        let mut subscribe_message = json!({
-            "action": "subscribe",
-            "channels": []
+    // same as get_book, but the snapshot arrives as a BookSnapshot event next to the
+    // book channel updates, which is what LocalBook needs to resync
+    pub async fn request_book(&self, market: &str) -> Result<(), tungstenite::Error> {
+        let markets_message = json!({
+            "action": "getBook",
+            "market": market,
         });
+        self.send(tungstenite::Message::Text(
+            markets_message.to_string().into(),
+        ))
+        .await
+    }
 
-        fn append_channel(
-            subscribe_message: &mut serde_json::Value,
-            subscribe_builder: &SubscriptionBuilder,
-            name: &str,
-        ) {
-            subscribe_message
-                .as_object_mut()
-                .unwrap()
-                .get_mut("channels")
-                .unwrap()
-                .as_array_mut()
-                .unwrap()
-                .push(json!({
-                    "name": name,
-                    "markets": [ subscribe_builder.market ],
-                }));
-        }
-
-        if subscribe_builder.trades {
-            append_channel(&mut subscribe_message, &subscribe_builder, "trades");
-        }
-        if subscribe_builder.account {
-            append_channel(&mut subscribe_message, &subscribe_builder, "account");
-        }
-        if subscribe_builder.book {
-            append_channel(&mut subscribe_message, &subscribe_builder, "book");
-        }
-        if subscribe_builder.ticker {
-            append_channel(&mut subscribe_message, &subscribe_builder, "ticker");
-        }
-        if subscribe_builder.candles {
-            subscribe_message
-                .as_object_mut()
-                .unwrap()
-                .get_mut("channels")
-                .unwrap()
-                .as_array_mut()
-                .unwrap()
-                .push(json!({
-                    "name": "candles",
-                    "interval": [ subscribe_builder.candles_interval ],
-                    "markets": [ subscribe_builder.market ],
-                }));
-        }
-
-        self.stream
-            .send(tungstenite::Message::Text(
-                subscribe_message.to_string().into(),
-            ))
-            .await
+    pub async fn subscribe(&self, subscription: Subscription) -> Result<(), tungstenite::Error> {
+        let subscribe_message = subscription.to_message("subscribe");
+        self.send(tungstenite::Message::Text(
+            subscribe_message.to_string().into(),
+        ))
+        .await?;
+        self.subscriptions.lock().unwrap().add(&subscription);
+        Ok(())
     }
 
-    pub async fn pong(&mut self, bytes: Bytes) -> Result<(), tungstenite::Error> {
-        self.stream.send(tungstenite::Message::Pong(bytes)).await
+    pub async fn unsubscribe(&self, subscription: Subscription) -> Result<(), tungstenite::Error> {
+        let unsubscribe_message = subscription.to_message("unsubscribe");
+        self.send(tungstenite::Message::Text(
+            unsubscribe_message.to_string().into(),
+        ))
+        .await?;
+        self.subscriptions.lock().unwrap().remove(&subscription);
+        Ok(())
     }
 
-    pub async fn get_markets(&mut self) -> Result<(), tungstenite::Error> {
+    // a snapshot of the channels subscribed to through this client
+    pub fn subscriptions(&self) -> SubscriptionRegistry {
+        self.subscriptions.lock().unwrap().clone()
+    }
+
+    pub async fn pong(&self, bytes: Bytes) -> Result<(), tungstenite::Error> {
+        self.send(tungstenite::Message::Pong(bytes)).await
+    }
+
+    pub async fn get_markets(&self) -> Result<Vec<Market>, BitvavoError> {
         let markets_message = json!({
             "action": "getMarkets",
         });
-        self.stream
-            .send(tungstenite::Message::Text(
-                markets_message.to_string().into(),
-            ))
-            .await
+        match self.request(markets_message).await? {
+            BitvavoEvent::Markets(markets) => Ok(markets),
+            other => Err(BitvavoError::UnexpectedResponse(Box::new(other))),
+        }
+    }
+
+    // the server time, in milliseconds since the epoch
+    pub async fn get_time(&self) -> Result<u64, BitvavoError> {
+        let get_time = json!({
+            "action": "getTime",
+        });
+        match self.request(get_time).await? {
+            BitvavoEvent::Time(time) => Ok(time),
+            other => Err(BitvavoError::UnexpectedResponse(Box::new(other))),
+        }
+    }
+
+    // one asset, or all of them
+    pub async fn get_assets(&self, symbol: Option<&str>) -> Result<Vec<Asset>, BitvavoError> {
+        let mut get_assets = json!({
+            "action": "getAssets",
+        });
+        if let Some(symbol) = symbol {
+            get_assets["symbol"] = json!(symbol);
+        }
+        match self.request(get_assets).await? {
+            BitvavoEvent::Assets(assets) => Ok(assets),
+            other => Err(BitvavoError::UnexpectedResponse(Box::new(other))),
+        }
+    }
+
+    pub async fn get_ticker24h(
+        &self,
+        market: Option<&str>,
+    ) -> Result<Vec<Ticker24h>, BitvavoError> {
+        let mut get_ticker24h = json!({
+            "action": "getTicker24h",
+        });
+        if let Some(market) = market {
+            get_ticker24h["market"] = json!(market);
+        }
+        match self.request(get_ticker24h).await? {
+            BitvavoEvent::Ticker24h(tickers) => Ok(tickers),
+            other => Err(BitvavoError::UnexpectedResponse(Box::new(other))),
+        }
+    }
+
+    pub async fn get_ticker_price(
+        &self,
+        market: Option<&str>,
+    ) -> Result<Vec<TickerPrice>, BitvavoError> {
+        let mut get_ticker_price = json!({
+            "action": "getTickerPrice",
+        });
+        if let Some(market) = market {
+            get_ticker_price["market"] = json!(market);
+        }
+        match self.request(get_ticker_price).await? {
+            BitvavoEvent::TickerPrices(prices) => Ok(prices),
+            other => Err(BitvavoError::UnexpectedResponse(Box::new(other))),
+        }
+    }
+
+    pub async fn get_trades(
+        &self,
+        market: &str,
+        range: RangeFilter,
+    ) -> Result<Vec<Trade>, BitvavoError> {
+        let mut get_trades = json!({
+            "action": "getTrades",
+            "market": market,
+        });
+        range.apply(&mut get_trades);
+        match self.request(get_trades).await? {
+            BitvavoEvent::Trades(mut trades) => {
+                for trade in &mut trades {
+                    trade.market = market.to_string();
+                }
+                Ok(trades)
+            }
+            other => Err(BitvavoError::UnexpectedResponse(Box::new(other))),
+        }
     }
 
-    pub async fn get_balances(&mut self) -> Result<(), tungstenite::Error> {
+    pub async fn get_candles(
+        &self,
+        market: &str,
+        interval: Interval,
+        range: RangeFilter,
+    ) -> Result<Vec<Candle>, BitvavoError> {
+        let mut get_candles = json!({
+            "action": "getCandles",
+            "market": market,
+            "interval": interval.as_str(),
+        });
+        range.apply(&mut get_candles);
+        match self.request(get_candles).await? {
+            BitvavoEvent::Candles(candles) => Ok(candles),
+            other => Err(BitvavoError::UnexpectedResponse(Box::new(other))),
+        }
+    }
+
+    pub async fn get_balances(&self) -> Result<HashMap<String, Balance>, BitvavoError> {
         // this will return ALL the non-zero balances
         let get_balances = json!({
             "action": "privateGetBalance",
         });
-        self.stream
-            .send(tungstenite::Message::Text(get_balances.to_string().into()))
-            .await
+        match self.request(get_balances).await? {
+            BitvavoEvent::Balances(balances) => Ok(balances),
+            other => Err(BitvavoError::UnexpectedResponse(Box::new(other))),
+        }
     }
 
-    pub async fn place_buy_limit_order(
-        &mut self,
+    // the trades of the account in one market
+    pub async fn get_account_trades(
+        &self,
         market: &str,
-        quantity: FloatWrapper,
-        price: FloatWrapper,
-    ) -> Result<(), tungstenite::Error> {
-        let order_message = json!({
-            "action": "placeOrder",
+        range: RangeFilter,
+    ) -> Result<Vec<AccountTrade>, BitvavoError> {
+        let mut get_trades = json!({
+            "action": "privateGetTrades",
             "market": market,
-            "side": "buy",
-            "orderType": "limit",
-            "amount": quantity.to_string(),
-            "price": price.to_string(),
         });
+        range.apply(&mut get_trades);
+        match self.request(get_trades).await? {
+            BitvavoEvent::AccountTrades(trades) => Ok(trades),
+            other => Err(BitvavoError::UnexpectedResponse(Box::new(other))),
+        }
+    }
+
+    // the fee tier of the account
+    pub async fn get_account(&self) -> Result<Account, BitvavoError> {
+        let get_account = json!({
+            "action": "privateGetAccount",
+        });
+        match self.request(get_account).await? {
+            BitvavoEvent::Account(account) => Ok(account),
+            other => Err(BitvavoError::UnexpectedResponse(Box::new(other))),
+        }
+    }
+
+    pub async fn get_deposit_history(
+        &self,
+        symbol: Option<&str>,
+        range: RangeFilter,
+    ) -> Result<Vec<Deposit>, BitvavoError> {
+        let mut get_deposits = json!({
+            "action": "privateGetDepositHistory",
+        });
+        if let Some(symbol) = symbol {
+            get_deposits["symbol"] = json!(symbol);
+        }
+        range.apply(&mut get_deposits);
+        match self.request(get_deposits).await? {
+            BitvavoEvent::Deposits(deposits) => Ok(deposits),
+            other => Err(BitvavoError::UnexpectedResponse(Box::new(other))),
+        }
+    }
+
+    pub async fn get_withdrawal_history(
+        &self,
+        symbol: Option<&str>,
+        range: RangeFilter,
+    ) -> Result<Vec<Withdrawal>, BitvavoError> {
+        let mut get_withdrawals = json!({
+            "action": "privateGetWithdrawalHistory",
+        });
+        if let Some(symbol) = symbol {
+            get_withdrawals["symbol"] = json!(symbol);
+        }
+        range.apply(&mut get_withdrawals);
+        match self.request(get_withdrawals).await? {
+            BitvavoEvent::Withdrawals(withdrawals) => Ok(withdrawals),
+            other => Err(BitvavoError::UnexpectedResponse(Box::new(other))),
+        }
+    }
+
+    // where to deposit `symbol` to
+    pub async fn deposit_assets(&self, symbol: &str) -> Result<DepositAddress, BitvavoError> {
+        let deposit_assets = json!({
+            "action": "privateDepositAssets",
+            "symbol": symbol,
+        });
+        match self.request(deposit_assets).await? {
+            BitvavoEvent::DepositAddress(address) => Ok(address),
+            other => Err(BitvavoError::UnexpectedResponse(Box::new(other))),
+        }
+    }
+
+    pub async fn place_order(&self, order_request: OrderRequest) -> Result<Order, BitvavoError> {
+        match self.request(order_request.to_message()).await? {
+            BitvavoEvent::PlacedOrder(order) => Ok(*order),
+            other => Err(BitvavoError::UnexpectedResponse(Box::new(other))),
+        }
+    }
 
-        self.stream
-            .send(tungstenite::Message::Text(order_message.to_string().into()))
+    pub async fn place_buy_limit_order(
+        &self,
+        market: &str,
+        quantity: FloatWrapper,
+        price: FloatWrapper,
+    ) -> Result<Order, BitvavoError> {
+        self.place_limit_order(market, Side::Buy, quantity, price)
             .await
     }
 
     pub async fn place_sell_limit_order(
-        &mut self,
+        &self,
         market: &str,
         quantity: FloatWrapper,
         price: FloatWrapper,
-    ) -> Result<(), tungstenite::Error> {
-        let order_message = json!({
-            "action": "placeOrder",
-            "market": market,
-            "side": "sell",
-            "orderType": "limit",
-            "amount": quantity.to_string(),
-            "price": price.to_string(),
-        });
-
-        self.stream
-            .send(tungstenite::Message::Text(order_message.to_string().into()))
+    ) -> Result<Order, BitvavoError> {
+        self.place_limit_order(market, Side::Sell, quantity, price)
             .await
     }
 
     pub async fn place_buy_market_order(
-        &mut self,
+        &self,
         market: &str,
         quantity: FloatWrapper,
-    ) -> Result<(), tungstenite::Error> {
-        let order_message = json!({
-            "action": "placeOrder",
-            "market": market,
-            "side": "buy",
-            "orderType": "market",
-            "amount": quantity.to_string(),
-        });
-
-        self.stream
-            .send(tungstenite::Message::Text(order_message.to_string().into()))
-            .await
+    ) -> Result<Order, BitvavoError> {
+        self.place_market_order(market, Side::Buy, quantity).await
     }
 
     pub async fn place_sell_market_order(
-        &mut self,
+        &self,
         market: &str,
         quantity: FloatWrapper,
-    ) -> Result<(), tungstenite::Error> {
-        let order_message = json!({
-            "action": "placeOrder",
+    ) -> Result<Order, BitvavoError> {
+        self.place_market_order(market, Side::Sell, quantity).await
+    }
+
+    async fn place_limit_order(
+        &self,
+        market: &str,
+        side: Side,
+        quantity: FloatWrapper,
+        price: FloatWrapper,
+    ) -> Result<Order, BitvavoError> {
+        let order_request = OrderRequestBuilder::default()
+            .with_market(market.to_string())
+            .with_side(side)
+            .with_order_type(OrderType::Limit)
+            .with_amount(quantity)
+            .with_price(price)
+            .build()?;
+        self.place_order(order_request).await
+    }
+
+    async fn place_market_order(
+        &self,
+        market: &str,
+        side: Side,
+        quantity: FloatWrapper,
+    ) -> Result<Order, BitvavoError> {
+        let order_request = OrderRequestBuilder::default()
+            .with_market(market.to_string())
+            .with_side(side)
+            .with_order_type(OrderType::Market)
+            .with_amount(quantity)
+            .build()?;
+        self.place_order(order_request).await
+    }
+
+    pub async fn update_order(
+        &self,
+        order_update: OrderUpdateRequest,
+    ) -> Result<Order, BitvavoError> {
+        match self.request(order_update.to_message()).await? {
+            BitvavoEvent::UpdatedOrder(order) => Ok(*order),
+            other => Err(BitvavoError::UnexpectedResponse(Box::new(other))),
+        }
+    }
+
+    pub async fn get_order(&self, market: &str, order_id: &str) -> Result<Order, BitvavoError> {
+        let get_order = json!({
+            "action": "privateGetOrder",
             "market": market,
-            "side": "sell",
-            "orderType": "market",
-            "amount": quantity.to_string(),
+            "orderId": order_id,
         });
+        match self.request(get_order).await? {
+            BitvavoEvent::Order(order) => Ok(*order),
+            other => Err(BitvavoError::UnexpectedResponse(Box::new(other))),
+        }
+    }
 
-        self.stream
-            .send(tungstenite::Message::Text(order_message.to_string().into()))
-            .await
+    pub async fn get_orders(&self, filter: OrdersFilter) -> Result<Vec<Order>, BitvavoError> {
+        self.orders(filter.to_message()).await
+    }
+
+    // the open orders of one market, or of all of them
+    pub async fn get_orders_open(&self, market: Option<&str>) -> Result<Vec<Order>, BitvavoError> {
+        let mut get_orders_open = json!({
+            "action": "privateGetOrdersOpen",
+        });
+        if let Some(market) = market {
+            get_orders_open["market"] = json!(market);
+        }
+        self.orders(get_orders_open).await
     }
 
-    pub async fn cancel_order(&mut self, order_id: &str) -> Result<(), tungstenite::Error> {
+    async fn orders(&self, orders_message: serde_json::Value) -> Result<Vec<Order>, BitvavoError> {
+        match self.request(orders_message).await? {
+            BitvavoEvent::Orders(orders) => Ok(orders),
+            other => Err(BitvavoError::UnexpectedResponse(Box::new(other))),
+        }
+    }
+
+    // returns the id of the cancelled order
+    pub async fn cancel_order(&self, order_id: &str) -> Result<String, BitvavoError> {
         let cancel_message = json!({
-            "action": "cancelOrder",
+            "action": "privateCancelOrder",
             "orderId": order_id,
         });
-
-        self.stream
-            .send(tungstenite::Message::Text(
-                cancel_message.to_string().into(),
-            ))
-            .await
+        match self.request(cancel_message).await? {
+            BitvavoEvent::CancelledOrder(order_id) => Ok(order_id),
+            other => Err(BitvavoError::UnexpectedResponse(Box::new(other))),
+        }
     }
 
-    pub async fn cancel_all(&mut self) -> Result<(), tungstenite::Error> {
+    // returns the ids of the cancelled orders
+    pub async fn cancel_all(&self) -> Result<Vec<String>, BitvavoError> {
         let cancel_all_message = json!({
-            "action": "cancelOrders",
+            "action": "privateCancelOrders",
         });
-        self.stream
-            .send(tungstenite::Message::Text(
-                cancel_all_message.to_string().into(),
-            ))
-            .await
+        self.cancel_orders(cancel_all_message).await
     }
 
     pub async fn cancel_all_within_market(
-        &mut self,
+        &self,
         market: &str,
-    ) -> Result<(), tungstenite::Error> {
+    ) -> Result<Vec<String>, BitvavoError> {
         let cancel_all_message = json!({
-            "action": "cancelOrders",
+            "action": "privateCancelOrders",
             "market": market,
         });
-        self.stream
-            .send(tungstenite::Message::Text(
-                cancel_all_message.to_string().into(),
-            ))
-            .await
+        self.cancel_orders(cancel_all_message).await
+    }
+
+    async fn cancel_orders(
+        &self,
+        cancel_all_message: serde_json::Value,
+    ) -> Result<Vec<String>, BitvavoError> {
+        match self.request(cancel_all_message).await? {
+            BitvavoEvent::CancelledOrders(order_ids) => Ok(order_ids),
+            other => Err(BitvavoError::UnexpectedResponse(Box::new(other))),
+        }
+    }
+}
+
+async fn read_loop(
+    mut read_stream: ReadStream,
+    write_stream: Arc<Mutex<WriteStream>>,
+    pending: Pending,
+    events: mpsc::UnboundedSender<Result<BitvavoEvent, DecodeError>>,
+) {
+    loop {
+        let msg = tokio::select! {
+            msg = read_stream.next() => msg,
+            // nobody takes the events anymore, no need to keep the socket alive
+            _ = events.closed() => {
+                log::info!("event receiver dropped, stopping the reader");
+                break;
+            }
+        };
+        let Some(msg) = msg else {
+            break;
+        };
+        match msg {
+            Ok(tungstenite::Message::Text(text)) => route(&text, &pending, &events),
+            Ok(tungstenite::Message::Ping(bytes)) => {
+                let pong = tungstenite::Message::Pong(bytes);
+                if let Err(e) = write_stream.lock().await.send(pong).await {
+                    log::error!("failed to pong: {:?}", e);
+                    break;
+                }
+            }
+            Ok(tungstenite::Message::Close(_)) => {
+                log::error!("server closed the connection");
+                break;
+            }
+            Ok(_) => {}
+            Err(e) => {
+                log::error!("error reading from the socket: {:?}", e);
+                break;
+            }
+        }
+    }
+    // dropping the senders wakes up whoever still waits for a response
+    let mut pending = pending.lock().unwrap();
+    pending.closed = true;
+    pending.waiting.clear();
+}
+
+fn route(
+    message: &str,
+    pending: &Pending,
+    events: &mpsc::UnboundedSender<Result<BitvavoEvent, DecodeError>>,
+) {
+    let (request_id, decoded) = decode_response(message);
+    let waiting = request_id.and_then(|id| pending.lock().unwrap().waiting.remove(&id));
+    match waiting {
+        // the caller might have given up on the response, nothing to do then
+        Some(tx) => _ = tx.send(decoded),
+        None => _ = events.send(decoded),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn route_response_to_pending_request() {
+        let pending = Pending::default();
+        let (events_tx, mut events_rx) = mpsc::unbounded_channel();
+        let (tx, mut rx) = oneshot::channel();
+        pending.lock().unwrap().waiting.insert(7, tx);
+
+        let response = json!({
+            "action": "privateGetBalance",
+            "requestId": 7,
+            "response": [{ "symbol": "BTC", "available": "1.5", "inOrder": "0.0" }],
+        });
+        route(&response.to_string(), &pending, &events_tx);
+
+        match rx.try_recv() {
+            Ok(Ok(BitvavoEvent::Balances(balances))) => assert!(balances.contains_key("BTC")),
+            other => panic!("unexpected: {:?}", other),
+        }
+        assert!(pending.lock().unwrap().waiting.is_empty());
+        assert!(events_rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn route_unsolicited_to_events() {
+        let pending = Pending::default();
+        let (events_tx, mut events_rx) = mpsc::unbounded_channel();
+
+        route(r#"{"event": "subscribed"}"#, &pending, &events_tx);
+
+        match events_rx.try_recv() {
+            Ok(Ok(BitvavoEvent::Subscribed)) => {}
+            other => panic!("unexpected: {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn requests_time_out_and_fail_once_closed() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        let client = tokio::spawn(tokio_tungstenite::connect_async(url));
+        let (tcp, _) = listener.accept().await.unwrap();
+        let mut ws = tokio_tungstenite::accept_async(tcp).await.unwrap();
+        let (write, read) = client.await.unwrap().unwrap().0.split();
+        let (bitvavo, mut events) = Bitvavo::wrap(write, read);
+        let bitvavo = bitvavo.with_timeout(Duration::from_millis(50));
+
+        // the exchange never answers
+        assert!(matches!(
+            bitvavo.get_balances().await,
+            Err(BitvavoError::Timeout)
+        ));
+        assert!(bitvavo.pending.lock().unwrap().waiting.is_empty());
+
+        ws.close(None).await.unwrap();
+        drop(ws);
+        // the reader is done once the events end
+        while events.recv().await.is_some() {}
+        assert!(matches!(
+            bitvavo.get_balances().await,
+            Err(BitvavoError::ConnectionClosed)
+        ));
     }
 }
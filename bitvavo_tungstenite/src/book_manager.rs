@@ -0,0 +1,169 @@
+use crate::decimal::Decimal;
+use crate::event::Ticker;
+use crate::local_book::{BookSync, LocalBook};
+use crate::price_level::{Book, PriceLevel};
+use std::collections::{BTreeMap, HashMap};
+
+// the best levels of one market, as seen by the consolidated view
+#[derive(Debug, Clone)]
+pub struct TopOfBook {
+    pub bid: Option<PriceLevel>,
+    pub ask: Option<PriceLevel>,
+    pub spread: Decimal,
+    pub in_sync: bool,
+}
+
+// one LocalBook per market, fed by the book and ticker events of all of them
+#[derive(Debug, Default)]
+pub struct BookManager {
+    books: HashMap<String, LocalBook>,
+}
+
+impl BookManager {
+    pub fn book(&self, market: &str) -> Option<&LocalBook> {
+        self.books.get(market)
+    }
+
+    pub fn markets(&self) -> impl Iterator<Item = &String> {
+        self.books.keys()
+    }
+
+    pub fn ingest_book(&mut self, book: Book) -> BookSync {
+        self.book_mut(&book.market).ingest_book(book)
+    }
+
+    pub fn ingest_snapshot(&mut self, book: Book) -> BookSync {
+        self.book_mut(&book.market).ingest_snapshot(book)
+    }
+
+    // a market subscribed to both book and ticker keeps its depth, the ticker
+    // only fills in the top while the book isn't in sync
+    pub fn ingest_ticker(&mut self, ticker: Ticker) {
+        self.book_mut(&ticker.market).ingest_ticker(ticker)
+    }
+
+    // drops the book of a market, e.g. after unsubscribing from it
+    pub fn remove(&mut self, market: &str) -> Option<LocalBook> {
+        self.books.remove(market)
+    }
+
+    // drops every book, e.g. after a disconnect
+    pub fn clear(&mut self) {
+        self.books.clear()
+    }
+
+    // the top of every book, by market
+    pub fn top_of_books(&self) -> BTreeMap<&str, TopOfBook> {
+        self.books
+            .iter()
+            .map(|(market, book)| {
+                let top = TopOfBook {
+                    bid: book.top_bid().cloned(),
+                    ask: book.top_ask().cloned(),
+                    spread: book.real_spread_or_default(),
+                    in_sync: book.is_in_sync(),
+                };
+                (market.as_str(), top)
+            })
+            .collect()
+    }
+
+    fn book_mut(&mut self, market: &str) -> &mut LocalBook {
+        self.books.entry(market.to_string()).or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::side::Side;
+    use serde_json::json;
+
+    fn book(market: &str, nonce: u64, bid: &str, ask: &str) -> Book {
+        serde_json::from_value(json!({
+            "market": market,
+            "nonce": nonce,
+            "bids": [[bid, "1"]],
+            "asks": [[ask, "1"]],
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn route_by_market() {
+        let mut manager = BookManager::default();
+        manager.ingest_snapshot(book("BTC-EUR", 1, "100", "101"));
+        manager.ingest_snapshot(book("ETH-EUR", 7, "10", "11"));
+
+        assert_eq!(
+            manager.ingest_book(book("ETH-EUR", 8, "10.5", "10.9")),
+            BookSync::InSync
+        );
+        assert_eq!(
+            manager.ingest_book(book("BTC-EUR", 3, "100.5", "101")),
+            BookSync::NeedsSnapshot
+        );
+
+        let ticker: Ticker = serde_json::from_value(json!({
+            "market": "SOL-EUR",
+            "bestBid": "20",
+            "bestBidSize": "5",
+            "bestAsk": "21",
+            "bestAskSize": "3",
+        }))
+        .unwrap();
+        manager.ingest_ticker(ticker);
+
+        let tops = manager.top_of_books();
+        assert_eq!(
+            tops.keys().copied().collect::<Vec<_>>(),
+            vec!["BTC-EUR", "ETH-EUR", "SOL-EUR"]
+        );
+        assert!(!tops["BTC-EUR"].in_sync);
+        assert_eq!(
+            tops["ETH-EUR"].bid.as_ref().unwrap().price.to_string(),
+            "10.5"
+        );
+        assert_eq!(
+            tops["SOL-EUR"].ask.as_ref().unwrap().quantity.to_string(),
+            "3"
+        );
+
+        manager.remove("SOL-EUR");
+        assert!(manager.book("SOL-EUR").is_none());
+        assert_eq!(manager.markets().count(), 2);
+    }
+
+    #[test]
+    fn mix_book_and_ticker_events() {
+        let ticker = |bid: &str, ask: &str| -> Ticker {
+            serde_json::from_value(json!({
+                "market": "BTC-EUR",
+                "bestBid": bid,
+                "bestBidSize": "2",
+                "bestAsk": ask,
+                "bestAskSize": "2",
+            }))
+            .unwrap()
+        };
+        let mut manager = BookManager::default();
+        // before the snapshot the ticker is all there is
+        manager.ingest_ticker(ticker("99.5", "100.5"));
+        assert_eq!(
+            manager.book("BTC-EUR").unwrap().mid_price(),
+            Some(Decimal::from(100))
+        );
+
+        let mut snapshot = book("BTC-EUR", 1, "100", "101");
+        snapshot.bids.extend(book("BTC-EUR", 1, "99", "102").bids);
+        manager.ingest_snapshot(snapshot);
+        manager.ingest_ticker(ticker("100", "101"));
+        manager.ingest_book(book("BTC-EUR", 2, "100.2", "100.8"));
+        manager.ingest_ticker(ticker("100.2", "100.8"));
+
+        let book = manager.book("BTC-EUR").unwrap();
+        assert_eq!(book.levels(Side::Buy).count(), 3);
+        assert_eq!(book.levels(Side::Sell).count(), 2);
+        assert_eq!(book.top_bid().unwrap().quantity.to_string(), "1");
+    }
+}
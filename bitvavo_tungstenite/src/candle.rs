@@ -1,22 +1,178 @@
+use crate::decimal::Decimal;
 use serde::{Deserialize, Serialize};
 use std::fmt;
 use std::fmt::{Display, Formatter};
+use std::str::FromStr;
 
+// the candle intervals the exchange accepts
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum Interval {
+    OneMinute,
+    FiveMinutes,
+    FifteenMinutes,
+    ThirtyMinutes,
+    OneHour,
+    TwoHours,
+    FourHours,
+    SixHours,
+    EightHours,
+    TwelveHours,
+    OneDay,
+    OneWeek,
+}
+
+impl Interval {
+    pub const ALL: [Interval; 12] = [
+        Interval::OneMinute,
+        Interval::FiveMinutes,
+        Interval::FifteenMinutes,
+        Interval::ThirtyMinutes,
+        Interval::OneHour,
+        Interval::TwoHours,
+        Interval::FourHours,
+        Interval::SixHours,
+        Interval::EightHours,
+        Interval::TwelveHours,
+        Interval::OneDay,
+        Interval::OneWeek,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Interval::OneMinute => "1m",
+            Interval::FiveMinutes => "5m",
+            Interval::FifteenMinutes => "15m",
+            Interval::ThirtyMinutes => "30m",
+            Interval::OneHour => "1h",
+            Interval::TwoHours => "2h",
+            Interval::FourHours => "4h",
+            Interval::SixHours => "6h",
+            Interval::EightHours => "8h",
+            Interval::TwelveHours => "12h",
+            Interval::OneDay => "1d",
+            Interval::OneWeek => "1W",
+        }
+    }
+
+    pub fn millis(&self) -> u64 {
+        const MINUTE: u64 = 60_000;
+        match self {
+            Interval::OneMinute => MINUTE,
+            Interval::FiveMinutes => 5 * MINUTE,
+            Interval::FifteenMinutes => 15 * MINUTE,
+            Interval::ThirtyMinutes => 30 * MINUTE,
+            Interval::OneHour => 60 * MINUTE,
+            Interval::TwoHours => 2 * 60 * MINUTE,
+            Interval::FourHours => 4 * 60 * MINUTE,
+            Interval::SixHours => 6 * 60 * MINUTE,
+            Interval::EightHours => 8 * 60 * MINUTE,
+            Interval::TwelveHours => 12 * 60 * MINUTE,
+            Interval::OneDay => 24 * 60 * MINUTE,
+            Interval::OneWeek => 7 * 24 * 60 * MINUTE,
+        }
+    }
+
+    // the start of the candle `timestamp` falls in; weeks start on Monday, the
+    // epoch was a Thursday
+    pub fn start_of(&self, timestamp: u64) -> u64 {
+        let offset = match self {
+            Interval::OneWeek => 4 * Interval::OneDay.millis(),
+            _ => 0,
+        };
+        let shifted = timestamp + self.millis() - offset;
+        // the first days after the epoch have no Monday before them
+        (shifted - shifted % self.millis() + offset).saturating_sub(self.millis())
+    }
+}
+
+impl FromStr for Interval {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Interval::ALL
+            .into_iter()
+            .find(|interval| interval.as_str() == s)
+            .ok_or_else(|| format!("unsupported candle interval {}", s))
+    }
+}
+
+impl TryFrom<String> for Interval {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<Interval> for String {
+    fn from(interval: Interval) -> Self {
+        interval.as_str().to_string()
+    }
+}
+
+impl Display for Interval {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+// a `candle` event; the exchange sends the rows as arrays, see Candle
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct CandleEvent {
     pub market: String,
-    pub candle: Vec<Vec<serde_json::Value>>,
-    pub interval: String,
+    pub interval: Interval,
+    #[serde(rename = "candle")]
+    pub candles: Vec<Candle>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct CandlesResponse {
+    action: String,
+    pub response: Vec<Candle>,
+}
+
+// [timestamp, open, high, low, close, volume], the timestamp being the start of
+// the interval in milliseconds
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(from = "CandleRow", into = "CandleRow")]
 pub struct Candle {
     pub timestamp: u64,
-    pub open: String,
-    pub high: String,
-    pub low: String,
-    pub close: String,
-    pub volume: String,
+    pub open: Decimal,
+    pub high: Decimal,
+    pub low: Decimal,
+    pub close: Decimal,
+    pub volume: Decimal,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CandleRow(u64, Decimal, Decimal, Decimal, Decimal, Decimal);
+
+impl From<CandleRow> for Candle {
+    fn from(row: CandleRow) -> Self {
+        let CandleRow(timestamp, open, high, low, close, volume) = row;
+        Candle {
+            timestamp,
+            open,
+            high,
+            low,
+            close,
+            volume,
+        }
+    }
+}
+
+impl From<Candle> for CandleRow {
+    fn from(candle: Candle) -> Self {
+        CandleRow(
+            candle.timestamp,
+            candle.open,
+            candle.high,
+            candle.low,
+            candle.close,
+            candle.volume,
+        )
+    }
 }
 
 impl Display for Candle {
@@ -29,15 +185,42 @@ impl Display for Candle {
     }
 }
 
-impl Candle {
-    pub fn from_serde_array(array: &[serde_json::Value]) -> Candle {
-        Candle {
-            timestamp: array[0].as_u64().unwrap(),
-            open: array[1].as_str().unwrap().to_string(),
-            high: array[2].as_str().unwrap().to_string(),
-            low: array[3].as_str().unwrap().to_string(),
-            close: array[4].as_str().unwrap().to_string(),
-            volume: array[5].as_str().unwrap().to_string(),
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_intervals() {
+        for interval in Interval::ALL {
+            assert_eq!(interval.as_str().parse(), Ok(interval));
         }
+        assert!("3m".parse::<Interval>().is_err());
+        assert_eq!(Interval::OneWeek.millis(), 604_800_000);
+
+        assert_eq!(
+            Interval::FifteenMinutes.start_of(1_700_000_123_456),
+            1_700_000_100_000
+        );
+        // Monday 2023-11-13 00:00 UTC
+        assert_eq!(
+            Interval::OneWeek.start_of(1_700_000_000_000),
+            1_699_833_600_000
+        );
+        assert_eq!(
+            Interval::OneWeek.start_of(1_699_833_600_000),
+            1_699_833_600_000
+        );
+        assert_eq!(Interval::OneWeek.start_of(1_000), 0);
+    }
+
+    #[test]
+    fn candle_round_trip() {
+        let row = serde_json::json!([1700000000000u64, "1.5", "2", "1", "1.75", "10.25"]);
+        let candle: Candle = serde_json::from_value(row.clone()).unwrap();
+        assert_eq!(candle.close, "1.75".parse().unwrap());
+        assert_eq!(serde_json::to_value(candle).unwrap(), row);
+
+        let short = serde_json::json!([1700000000000u64, "1.5", "2"]);
+        assert!(serde_json::from_value::<Candle>(short).is_err());
     }
 }
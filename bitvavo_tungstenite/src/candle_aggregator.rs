@@ -0,0 +1,420 @@
+use crate::candle::{Candle, Interval};
+use crate::decimal::Decimal;
+use crate::trade::Trade;
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use std::time::Duration;
+
+#[derive(Debug, PartialEq)]
+pub enum CandleAggregatorError {
+    NoPeriods,
+    // periods are whole milliseconds, like the exchange timestamps
+    ZeroPeriod,
+}
+
+impl Display for CandleAggregatorError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CandleAggregatorError::NoPeriods => write!(f, "no periods"),
+            CandleAggregatorError::ZeroPeriod => write!(f, "periods must be at least 1ms"),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct CandleAggregatorBuilder {
+    // the interval, when the buckets should line up with the exchange's candles
+    periods: Vec<(Duration, Option<Interval>)>,
+    grace: Duration,
+    empty_candles: bool,
+}
+
+impl CandleAggregatorBuilder {
+    pub fn with_interval(mut self, interval: Interval) -> Self {
+        self.periods
+            .push((Duration::from_millis(interval.millis()), Some(interval)));
+        self
+    }
+
+    // any bucket size, e.g. 10 seconds
+    pub fn with_period(mut self, period: Duration) -> Self {
+        self.periods.push((period, None));
+        self
+    }
+
+    // how long after its end a candle still takes late trades; the exchange
+    // timestamps of the trades are the clock
+    pub fn with_grace(mut self, grace: Duration) -> Self {
+        self.grace = grace;
+        self
+    }
+
+    // emit flat candles, without volume, for periods without trades
+    pub fn with_empty_candles(mut self) -> Self {
+        self.empty_candles = true;
+        self
+    }
+
+    pub fn build(self) -> Result<CandleAggregator, CandleAggregatorError> {
+        if self.periods.is_empty() {
+            return Err(CandleAggregatorError::NoPeriods);
+        }
+        let mut periods = Vec::<(u64, Option<Interval>)>::new();
+        for (period, interval) in self.periods {
+            let period = period.as_millis() as u64;
+            if period == 0 {
+                return Err(CandleAggregatorError::ZeroPeriod);
+            }
+            match periods.iter_mut().find(|(p, _)| *p == period) {
+                // an interval wins over a plain period of the same length
+                Some((_, known)) => *known = known.or(interval),
+                None => periods.push((period, interval)),
+            }
+        }
+        periods.sort_by_key(|(period, _)| *period);
+        Ok(CandleAggregator {
+            periods: periods
+                .into_iter()
+                .map(|(period, interval)| PeriodCandles::new(period, interval))
+                .collect(),
+            grace: self.grace.as_millis() as u64,
+            empty_candles: self.empty_candles,
+            watermark: 0,
+            late_trades: 0,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClosedCandle {
+    pub period: Duration,
+    pub candle: Candle,
+}
+
+// turns the trades of one market into candles for several periods at once
+#[derive(Debug, Clone)]
+pub struct CandleAggregator {
+    periods: Vec<PeriodCandles>,
+    grace: u64,
+    empty_candles: bool,
+    // the latest exchange timestamp seen
+    watermark: u64,
+    late_trades: u64,
+}
+
+impl CandleAggregator {
+    // adds a trade to the open candles and returns the candles it closed, in the
+    // order they ended; a trade older than the grace window allows is dropped
+    pub fn ingest(&mut self, trade: &Trade) -> Vec<ClosedCandle> {
+        let mut late = false;
+        for period in &mut self.periods {
+            late |= !period.add(trade, self.watermark, self.grace);
+        }
+        if late {
+            log::warn!("trade {} arrived too late for some candles", trade.id());
+            self.late_trades += 1;
+        }
+        self.advance(trade.timestamp())
+    }
+
+    // moves the clock without a trade, e.g. to the exchange time, so that quiet
+    // periods get closed too
+    pub fn advance(&mut self, timestamp: u64) -> Vec<ClosedCandle> {
+        self.watermark = self.watermark.max(timestamp);
+        let mut closed = Vec::new();
+        for period in &mut self.periods {
+            period.close(self.watermark, self.grace, self.empty_candles, &mut closed);
+        }
+        closed.sort_by_key(|c| (c.candle.timestamp + c.period.as_millis() as u64, c.period));
+        closed
+    }
+
+    // the trades dropped from at least one period for arriving too late
+    pub fn late_trades(&self) -> u64 {
+        self.late_trades
+    }
+}
+
+#[derive(Debug, Clone)]
+struct OpenCandle {
+    candle: Candle,
+    // the first and last trade timestamps, open and close follow them rather
+    // than the order the trades arrived in
+    first: u64,
+    last: u64,
+}
+
+#[derive(Debug, Clone)]
+struct PeriodCandles {
+    period: u64,
+    interval: Option<Interval>,
+    open: BTreeMap<u64, OpenCandle>,
+    // start and close of the last candle emitted, for the flat ones
+    last_closed: Option<(u64, Decimal)>,
+}
+
+impl PeriodCandles {
+    fn new(period: u64, interval: Option<Interval>) -> Self {
+        PeriodCandles {
+            period,
+            interval,
+            open: BTreeMap::new(),
+            last_closed: None,
+        }
+    }
+
+    // the start of the last candle that is closed at `watermark`
+    fn closed_until(&self, watermark: u64, grace: u64) -> Option<u64> {
+        let limit = watermark.checked_sub(self.period + grace)?;
+        Some(self.start_of(limit))
+    }
+
+    // intervals start where the exchange's candles do, weeks on Monday
+    fn start_of(&self, timestamp: u64) -> u64 {
+        match self.interval {
+            Some(interval) => interval.start_of(timestamp),
+            None => timestamp - timestamp % self.period,
+        }
+    }
+
+    // false when the candle of the trade is already closed
+    fn add(&mut self, trade: &Trade, watermark: u64, grace: u64) -> bool {
+        let timestamp = trade.timestamp();
+        let start = self.start_of(timestamp);
+        if self
+            .closed_until(watermark, grace)
+            .is_some_and(|closed| start <= closed)
+        {
+            return false;
+        }
+        let price = trade.price();
+        match self.open.get_mut(&start) {
+            Some(open) => {
+                let candle = &mut open.candle;
+                candle.high = candle.high.max(price);
+                candle.low = candle.low.min(price);
+                candle.volume += trade.amount();
+                if timestamp < open.first {
+                    candle.open = price;
+                    open.first = timestamp;
+                }
+                if timestamp >= open.last {
+                    candle.close = price;
+                    open.last = timestamp;
+                }
+            }
+            None => {
+                let candle = Candle {
+                    timestamp: start,
+                    open: price,
+                    high: price,
+                    low: price,
+                    close: price,
+                    volume: trade.amount(),
+                };
+                self.open.insert(
+                    start,
+                    OpenCandle {
+                        candle,
+                        first: timestamp,
+                        last: timestamp,
+                    },
+                );
+            }
+        }
+        true
+    }
+
+    fn close(
+        &mut self,
+        watermark: u64,
+        grace: u64,
+        empty_candles: bool,
+        closed: &mut Vec<ClosedCandle>,
+    ) {
+        let Some(closed_until) = self.closed_until(watermark, grace) else {
+            return;
+        };
+        let still_open = self.open.split_off(&(closed_until + 1));
+        for (start, open) in std::mem::replace(&mut self.open, still_open) {
+            if empty_candles {
+                self.fill(start, closed);
+            }
+            self.last_closed = Some((start, open.candle.close));
+            closed.push(self.closed(open.candle));
+        }
+        if empty_candles {
+            self.fill(closed_until + self.period, closed);
+        }
+    }
+
+    // flat candles from the last one emitted up to `until`, only once there
+    // has been a trade to take the price from
+    fn fill(&mut self, until: u64, closed: &mut Vec<ClosedCandle>) {
+        while let Some((last, price)) = self.last_closed
+            && last + self.period < until
+        {
+            let start = last + self.period;
+            self.last_closed = Some((start, price));
+            closed.push(self.closed(Candle {
+                timestamp: start,
+                open: price,
+                high: price,
+                low: price,
+                close: price,
+                volume: Decimal::ZERO,
+            }));
+        }
+    }
+
+    fn closed(&self, candle: Candle) -> ClosedCandle {
+        ClosedCandle {
+            period: Duration::from_millis(self.period),
+            candle,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trade(timestamp: u64, price: &str, amount: &str) -> Trade {
+        serde_json::from_value(serde_json::json!({
+            "timestamp": timestamp,
+            "id": timestamp.to_string(),
+            "amount": amount,
+            "price": price,
+            "side": "buy",
+        }))
+        .unwrap()
+    }
+
+    fn d(s: &str) -> Decimal {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn build_rejects_invalid_periods() {
+        assert_eq!(
+            CandleAggregatorBuilder::default().build().unwrap_err(),
+            CandleAggregatorError::NoPeriods
+        );
+        assert_eq!(
+            CandleAggregatorBuilder::default()
+                .with_period(Duration::from_micros(10))
+                .build()
+                .unwrap_err(),
+            CandleAggregatorError::ZeroPeriod
+        );
+    }
+
+    #[test]
+    fn aggregate_several_periods() {
+        let mut aggregator = CandleAggregatorBuilder::default()
+            .with_interval(Interval::OneMinute)
+            .with_period(Duration::from_secs(10))
+            .build()
+            .unwrap();
+
+        assert!(aggregator.ingest(&trade(1_000, "100", "1")).is_empty());
+        assert!(aggregator.ingest(&trade(5_000, "102", "2")).is_empty());
+        assert!(aggregator.ingest(&trade(8_000, "99", "1")).is_empty());
+
+        let closed = aggregator.ingest(&trade(12_000, "101", "1"));
+        assert_eq!(closed.len(), 1);
+        assert_eq!(closed[0].period, Duration::from_secs(10));
+        assert_eq!(
+            closed[0].candle,
+            Candle {
+                timestamp: 0,
+                open: d("100"),
+                high: d("102"),
+                low: d("99"),
+                close: d("99"),
+                volume: d("4"),
+            }
+        );
+
+        // closes the second 10s candle and the first minute
+        let closed = aggregator.ingest(&trade(61_000, "103", "1"));
+        let periods: Vec<_> = closed.iter().map(|c| c.period.as_secs()).collect();
+        assert_eq!(periods, vec![10, 60]);
+        assert_eq!(closed[1].candle.close, d("101"));
+        assert_eq!(closed[1].candle.volume, d("5"));
+    }
+
+    #[test]
+    fn late_trades_within_grace() {
+        let mut aggregator = CandleAggregatorBuilder::default()
+            .with_period(Duration::from_secs(10))
+            .with_grace(Duration::from_secs(2))
+            .build()
+            .unwrap();
+
+        aggregator.ingest(&trade(3_000, "100", "1"));
+        assert!(aggregator.ingest(&trade(11_000, "105", "1")).is_empty());
+        // older than the first trade, so it opens the candle
+        assert!(aggregator.ingest(&trade(1_000, "98", "1")).is_empty());
+
+        let closed = aggregator.ingest(&trade(12_000, "106", "1"));
+        assert_eq!(closed.len(), 1);
+        assert_eq!(closed[0].candle.open, d("98"));
+        assert_eq!(closed[0].candle.close, d("100"));
+
+        aggregator.ingest(&trade(9_000, "90", "1"));
+        assert_eq!(aggregator.late_trades(), 1);
+    }
+
+    #[test]
+    fn flat_candles_for_empty_periods() {
+        let mut aggregator = CandleAggregatorBuilder::default()
+            .with_period(Duration::from_secs(10))
+            .with_empty_candles()
+            .build()
+            .unwrap();
+
+        // nothing to take a price from yet
+        assert!(aggregator.advance(30_000).is_empty());
+
+        aggregator.ingest(&trade(31_000, "100", "1"));
+        let closed = aggregator.ingest(&trade(65_000, "101", "1"));
+        let starts: Vec<_> = closed.iter().map(|c| c.candle.timestamp).collect();
+        assert_eq!(starts, vec![30_000, 40_000, 50_000]);
+        assert_eq!(closed[2].candle.close, d("100"));
+        assert!(closed[2].candle.volume.is_zero());
+
+        // a quiet market still closes its candles
+        let closed = aggregator.advance(90_000);
+        let starts: Vec<_> = closed.iter().map(|c| c.candle.timestamp).collect();
+        assert_eq!(starts, vec![60_000, 70_000, 80_000]);
+        assert_eq!(closed[1].candle.open, d("101"));
+    }
+
+    #[test]
+    fn weeks_start_on_monday() {
+        // a Monday
+        const T0: u64 = 1_699_833_600_000;
+        const DAY: u64 = 24 * 60 * 60 * 1000;
+        let mut aggregator = CandleAggregatorBuilder::default()
+            .with_interval(Interval::OneWeek)
+            .build()
+            .unwrap();
+
+        // the Wednesday and the Sunday before are one week, the Thursday after
+        // starts the next
+        aggregator.ingest(&trade(T0 - 5 * DAY, "100", "1"));
+        aggregator.ingest(&trade(T0 - DAY, "101", "1"));
+        assert!(aggregator.advance(T0 - 1).is_empty());
+        let closed = aggregator.ingest(&trade(T0 + 3 * DAY, "102", "1"));
+        assert_eq!(closed.len(), 1);
+        assert_eq!(closed[0].period, Duration::from_millis(7 * DAY));
+        assert_eq!(closed[0].candle.timestamp, T0 - 7 * DAY);
+        assert_eq!(closed[0].candle.open, d("100"));
+        assert_eq!(closed[0].candle.close, d("101"));
+
+        let closed = aggregator.advance(T0 + 7 * DAY);
+        assert_eq!(closed[0].candle.timestamp, T0);
+        assert_eq!(closed[0].candle.volume, d("1"));
+    }
+}
@@ -0,0 +1,238 @@
+use crate::candle::{Candle, Interval};
+use crate::decimal::Decimal;
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+
+#[derive(Debug, PartialEq)]
+pub enum SeriesError {
+    // one interval isn't a whole number of the other, e.g. 12h and 8h
+    Incompatible { from: Interval, to: Interval },
+    // the candle doesn't start where a candle of the interval would
+    Misaligned { timestamp: u64, interval: Interval },
+}
+
+impl Display for SeriesError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SeriesError::Incompatible { from, to } => {
+                write!(f, "cannot resample {} candles to {}", from, to)
+            }
+            SeriesError::Misaligned {
+                timestamp,
+                interval,
+            } => write!(f, "{} is not the start of a {} candle", timestamp, interval),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GapFill {
+    // repeats the prices of the previous candle
+    ForwardFill,
+    // open, high, low and close all at the previous close
+    Flat,
+}
+
+// oldest first with one candle per timestamp, the later one winning; getCandles
+// returns the most recent first
+pub fn sorted(candles: &[Candle]) -> Vec<Candle> {
+    candles
+        .iter()
+        .map(|c| (c.timestamp, *c))
+        .collect::<BTreeMap<_, _>>()
+        .into_values()
+        .collect()
+}
+
+// converts `from` candles to `to` candles, oldest first. Going up merges the
+// candles of each period, the first and last one possibly partial. Going down
+// can't recover what happened within a candle: it becomes the first of its
+// smaller candles, the others are flat at its close without volume
+pub fn resample(
+    candles: &[Candle],
+    from: Interval,
+    to: Interval,
+) -> Result<Vec<Candle>, SeriesError> {
+    let (larger, smaller) = (
+        from.millis().max(to.millis()),
+        from.millis().min(to.millis()),
+    );
+    if larger % smaller != 0 {
+        return Err(SeriesError::Incompatible { from, to });
+    }
+    let candles = sorted(candles);
+    if let Some(candle) = candles
+        .iter()
+        .find(|c| from.start_of(c.timestamp) != c.timestamp)
+    {
+        return Err(SeriesError::Misaligned {
+            timestamp: candle.timestamp,
+            interval: from,
+        });
+    }
+
+    if to.millis() >= from.millis() {
+        let mut merged = BTreeMap::<u64, Candle>::new();
+        for candle in candles {
+            merged
+                .entry(to.start_of(candle.timestamp))
+                .and_modify(|m| {
+                    m.high = m.high.max(candle.high);
+                    m.low = m.low.min(candle.low);
+                    m.close = candle.close;
+                    m.volume += candle.volume;
+                })
+                .or_insert(Candle {
+                    timestamp: to.start_of(candle.timestamp),
+                    ..candle
+                });
+        }
+        return Ok(merged.into_values().collect());
+    }
+
+    let mut split = Vec::with_capacity(candles.len() * (larger / smaller) as usize);
+    for candle in candles {
+        split.push(candle);
+        for i in 1..larger / smaller {
+            split.push(flat(candle.timestamp + i * smaller, candle.close));
+        }
+    }
+    Ok(split)
+}
+
+// the starts of the candles missing between the first and the last one
+pub fn missing_timestamps(candles: &[Candle], interval: Interval) -> Vec<u64> {
+    let candles = sorted(candles);
+    candles
+        .windows(2)
+        .flat_map(|pair| {
+            (pair[0].timestamp + interval.millis()..pair[1].timestamp)
+                .step_by(interval.millis() as usize)
+        })
+        .collect()
+}
+
+// the candles oldest first, with the missing ones between the first and the last
+// filled in; they never have volume
+pub fn fill_gaps(candles: &[Candle], interval: Interval, fill: GapFill) -> Vec<Candle> {
+    let candles = sorted(candles);
+    let mut filled = Vec::<Candle>::with_capacity(candles.len());
+    for candle in candles {
+        if let Some(previous) = filled.last().copied() {
+            for timestamp in (previous.timestamp + interval.millis()..candle.timestamp)
+                .step_by(interval.millis() as usize)
+            {
+                filled.push(match fill {
+                    GapFill::ForwardFill => Candle {
+                        timestamp,
+                        volume: Decimal::ZERO,
+                        ..previous
+                    },
+                    GapFill::Flat => flat(timestamp, previous.close),
+                });
+            }
+        }
+        filled.push(candle);
+    }
+    filled
+}
+
+fn flat(timestamp: u64, price: Decimal) -> Candle {
+    Candle {
+        timestamp,
+        open: price,
+        high: price,
+        low: price,
+        close: price,
+        volume: Decimal::ZERO,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MINUTE: u64 = 60_000;
+
+    fn candle(timestamp: u64, ohlcv: [&str; 5]) -> Candle {
+        let [open, high, low, close, volume] = ohlcv.map(|v| v.parse::<Decimal>().unwrap());
+        Candle {
+            timestamp,
+            open,
+            high,
+            low,
+            close,
+            volume,
+        }
+    }
+
+    #[test]
+    fn downsample() {
+        // most recent first, like getCandles
+        let candles = [
+            candle(6 * MINUTE, ["9", "9", "9", "9", "1"]),
+            candle(2 * MINUTE, ["11", "14", "10", "13", "2"]),
+            candle(MINUTE, ["12", "12", "8", "11", "3"]),
+            candle(0, ["10", "12", "9", "12", "1"]),
+        ];
+        let resampled = resample(&candles, Interval::OneMinute, Interval::FiveMinutes).unwrap();
+        assert_eq!(
+            resampled,
+            vec![
+                candle(0, ["10", "14", "8", "13", "6"]),
+                candle(5 * MINUTE, ["9", "9", "9", "9", "1"]),
+            ]
+        );
+    }
+
+    #[test]
+    fn upsample() {
+        let candles = [candle(0, ["10", "12", "9", "11", "4"])];
+        let resampled = resample(&candles, Interval::OneHour, Interval::FifteenMinutes).unwrap();
+        assert_eq!(resampled.len(), 4);
+        assert_eq!(resampled[0], candles[0]);
+        assert_eq!(
+            resampled[3],
+            candle(45 * MINUTE, ["11", "11", "11", "11", "0"])
+        );
+    }
+
+    #[test]
+    fn reject_incompatible_series() {
+        assert_eq!(
+            resample(&[], Interval::TwelveHours, Interval::EightHours),
+            Err(SeriesError::Incompatible {
+                from: Interval::TwelveHours,
+                to: Interval::EightHours,
+            })
+        );
+        let misaligned = [candle(MINUTE, ["1", "1", "1", "1", "1"])];
+        assert_eq!(
+            resample(&misaligned, Interval::FiveMinutes, Interval::OneHour),
+            Err(SeriesError::Misaligned {
+                timestamp: MINUTE,
+                interval: Interval::FiveMinutes,
+            })
+        );
+    }
+
+    #[test]
+    fn detect_and_fill_gaps() {
+        let candles = [
+            candle(0, ["10", "12", "9", "11", "1"]),
+            candle(3 * MINUTE, ["11", "11", "11", "11", "1"]),
+        ];
+        assert_eq!(
+            missing_timestamps(&candles, Interval::OneMinute),
+            vec![MINUTE, 2 * MINUTE]
+        );
+
+        let filled = fill_gaps(&candles, Interval::OneMinute, GapFill::ForwardFill);
+        assert_eq!(filled.len(), 4);
+        assert_eq!(filled[2], candle(2 * MINUTE, ["10", "12", "9", "11", "0"]));
+
+        let filled = fill_gaps(&candles, Interval::OneMinute, GapFill::Flat);
+        assert_eq!(filled[1], candle(MINUTE, ["11", "11", "11", "11", "0"]));
+        assert!(missing_timestamps(&filled, Interval::OneMinute).is_empty());
+    }
+}
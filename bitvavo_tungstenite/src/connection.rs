@@ -0,0 +1,403 @@
+use crate::bitvavo::{Bitvavo, BitvavoError, DEFAULT_REQUEST_TIMEOUT, EventReceiver};
+use crate::decode::DecodeError;
+use crate::event::{AuthRequest, BitvavoEvent};
+use crate::subscription::{Subscription, SubscriptionRegistry};
+use futures_util::StreamExt;
+use std::fmt::{Debug, Formatter};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio_tungstenite::connect_async;
+
+// events are by far the most frequent variant, boxing them would only add an allocation
+#[allow(clippy::large_enum_variant)]
+pub enum ConnectionEvent {
+    // the socket is up, the exchange accepted the credentials and the active
+    // subscriptions have been (re)sent
+    Connected,
+    Disconnected,
+    Reconnecting { attempt: u32, delay: Duration },
+    Event(Result<BitvavoEvent, DecodeError>),
+}
+
+impl Debug for ConnectionEvent {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConnectionEvent::Connected => write!(f, "Connected"),
+            ConnectionEvent::Disconnected => write!(f, "Disconnected"),
+            ConnectionEvent::Reconnecting { attempt, delay } => {
+                write!(f, "Reconnecting(attempt={}, delay={:?})", attempt, delay)
+            }
+            ConnectionEvent::Event(e) => write!(f, "Event({:?})", e),
+        }
+    }
+}
+
+pub type ConnectionEventReceiver = mpsc::UnboundedReceiver<ConnectionEvent>;
+
+#[derive(Debug, Clone)]
+pub struct ManagedConnectionBuilder {
+    url: String,
+    credentials: Option<(String, String)>,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    request_timeout: Duration,
+}
+
+impl Default for ManagedConnectionBuilder {
+    fn default() -> Self {
+        ManagedConnectionBuilder {
+            url: String::new(),
+            credentials: None,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(30),
+            request_timeout: DEFAULT_REQUEST_TIMEOUT,
+        }
+    }
+}
+
+impl ManagedConnectionBuilder {
+    pub fn with_url(mut self, url: String) -> Self {
+        self.url = url;
+        self
+    }
+
+    pub fn with_credentials(mut self, api_key: String, api_secret: String) -> Self {
+        self.credentials = Some((api_key, api_secret));
+        self
+    }
+
+    // the delay doubles after each failed attempt, up to `max`
+    pub fn with_backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.initial_backoff = initial;
+        self.max_backoff = max;
+        self
+    }
+
+    // see Bitvavo::with_timeout
+    pub fn with_request_timeout(mut self, timeout: Duration) -> Self {
+        self.request_timeout = timeout;
+        self
+    }
+
+    // spawns the task owning the socket lifecycle; it stops once the returned
+    // receiver is dropped
+    pub fn connect(self) -> (ManagedConnection, ConnectionEventReceiver) {
+        let connection = ManagedConnection {
+            current: Arc::default(),
+            subscriptions: Arc::default(),
+        };
+        let (events_tx, events_rx) = mpsc::unbounded_channel();
+
+        tokio::spawn(supervise(self, connection.clone(), events_tx));
+
+        (connection, events_rx)
+    }
+
+    fn backoff(&self, attempt: u32) -> Duration {
+        self.initial_backoff
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.max_backoff)
+    }
+}
+
+#[derive(Clone)]
+pub struct ManagedConnection {
+    current: Arc<RwLock<Option<Bitvavo>>>,
+    subscriptions: Arc<Mutex<SubscriptionRegistry>>,
+}
+
+impl ManagedConnection {
+    // the client of the live socket, None while (re)connecting
+    pub fn client(&self) -> Option<Bitvavo> {
+        self.current.read().unwrap().clone()
+    }
+
+    // remembers the subscription so it is replayed after a reconnect, and sends
+    // it right away when connected
+    pub async fn subscribe(&self, subscription: Subscription) -> Result<(), tungstenite::Error> {
+        self.subscriptions.lock().unwrap().add(&subscription);
+        match self.client() {
+            Some(bitvavo) => bitvavo.subscribe(subscription).await,
+            None => Ok(()),
+        }
+    }
+
+    // forgets the channels so they are not replayed anymore, and unsubscribes
+    // right away when connected
+    pub async fn unsubscribe(&self, subscription: Subscription) -> Result<(), tungstenite::Error> {
+        self.subscriptions.lock().unwrap().remove(&subscription);
+        match self.client() {
+            Some(bitvavo) => bitvavo.unsubscribe(subscription).await,
+            None => Ok(()),
+        }
+    }
+
+    // what gets replayed after a reconnect
+    pub fn subscriptions(&self) -> SubscriptionRegistry {
+        self.subscriptions.lock().unwrap().clone()
+    }
+}
+
+async fn supervise(
+    config: ManagedConnectionBuilder,
+    connection: ManagedConnection,
+    events: mpsc::UnboundedSender<ConnectionEvent>,
+) {
+    let mut attempt = 0;
+
+    while !events.is_closed() {
+        match connect_async(config.url.as_str()).await {
+            Ok((ws_stream, _)) => {
+                let (write, read) = ws_stream.split();
+                let (bitvavo, mut bitvavo_events) = Bitvavo::wrap(write, read);
+                let bitvavo = bitvavo.with_timeout(config.request_timeout);
+
+                let mut held = Vec::new();
+                let established = establish(
+                    &config,
+                    &connection,
+                    &bitvavo,
+                    &mut bitvavo_events,
+                    &mut held,
+                )
+                .await;
+                if established.is_ok() {
+                    attempt = 0;
+                    _ = events.send(ConnectionEvent::Connected);
+                }
+                for event in held {
+                    _ = events.send(ConnectionEvent::Event(event));
+                }
+
+                match established {
+                    Ok(()) => {
+                        let stopped = loop {
+                            tokio::select! {
+                                event = bitvavo_events.recv() => match event {
+                                    Some(event) => {
+                                        _ = events.send(ConnectionEvent::Event(event));
+                                    }
+                                    None => break false,
+                                },
+                                // the caller is gone, and with it the reason for the socket
+                                _ = events.closed() => break true,
+                            }
+                        };
+
+                        *connection.current.write().unwrap() = None;
+                        if stopped {
+                            if let Err(e) = bitvavo.close().await {
+                                log::debug!("failed to close the connection: {:?}", e);
+                            }
+                            break;
+                        }
+                        _ = events.send(ConnectionEvent::Disconnected);
+                    }
+                    Err(e) => log::error!("failed to set up the connection: {:?}", e),
+                }
+            }
+            Err(e) => log::error!("failed to connect: {:?}", e),
+        }
+
+        let delay = config.backoff(attempt);
+        attempt += 1;
+        if events
+            .send(ConnectionEvent::Reconnecting { attempt, delay })
+            .is_err()
+        {
+            break;
+        }
+        tokio::time::sleep(delay).await;
+    }
+    log::info!("connection manager stopped");
+}
+
+// the events that arrive while the connection is set up are kept in `held`, the
+// answer to the authentication among them
+async fn establish(
+    config: &ManagedConnectionBuilder,
+    connection: &ManagedConnection,
+    bitvavo: &Bitvavo,
+    bitvavo_events: &mut EventReceiver,
+    held: &mut Vec<Result<BitvavoEvent, DecodeError>>,
+) -> Result<(), BitvavoError> {
+    // a fresh request each time, the signature is only valid within its window
+    if let Some((api_key, api_secret)) = &config.credentials {
+        bitvavo
+            .authenticate(AuthRequest::make(api_key, api_secret))
+            .await?;
+        tokio::time::timeout(config.request_timeout, authenticated(bitvavo_events, held))
+            .await
+            .map_err(|_| BitvavoError::Timeout)??;
+    }
+    // published before the subscriptions are read: a subscribe from then on is
+    // either part of the replay or sent by ManagedConnection::subscribe itself
+    *connection.current.write().unwrap() = Some(bitvavo.clone());
+    let subscription = connection.subscriptions.lock().unwrap().to_subscription();
+    if let Some(subscription) = subscription
+        && let Err(e) = bitvavo.subscribe(subscription).await
+    {
+        *connection.current.write().unwrap() = None;
+        return Err(e.into());
+    }
+    Ok(())
+}
+
+// the exchange answers an authenticate with an event, not with a response
+async fn authenticated(
+    bitvavo_events: &mut EventReceiver,
+    held: &mut Vec<Result<BitvavoEvent, DecodeError>>,
+) -> Result<(), BitvavoError> {
+    while let Some(event) = bitvavo_events.recv().await {
+        let answer = match &event {
+            Ok(BitvavoEvent::Authenticated) => Some(Ok(())),
+            Ok(BitvavoEvent::Error {
+                action: Some(action),
+                error_code,
+                error,
+                ..
+            }) if action == "authenticate" => Some(Err(BitvavoError::Exchange {
+                error_code: *error_code,
+                error: error.clone(),
+            })),
+            _ => None,
+        };
+        held.push(event);
+        if let Some(answer) = answer {
+            return answer;
+        }
+    }
+    Err(BitvavoError::ConnectionClosed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bitvavo::SubscriptionBuilder;
+    use futures_util::SinkExt;
+    use tokio::net::TcpListener;
+    use tokio_tungstenite::accept_async;
+
+    async fn next_action(
+        ws: &mut tokio_tungstenite::WebSocketStream<tokio::net::TcpStream>,
+    ) -> String {
+        loop {
+            if let tungstenite::Message::Text(text) = ws.next().await.unwrap().unwrap() {
+                let value: serde_json::Value = serde_json::from_str(&text).unwrap();
+                return value["action"].as_str().unwrap().to_string();
+            }
+        }
+    }
+
+    async fn answer(
+        ws: &mut tokio_tungstenite::WebSocketStream<tokio::net::TcpStream>,
+        text: &str,
+    ) {
+        ws.send(tungstenite::Message::Text(text.into()))
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn reconnect_replays_authentication_and_subscriptions() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+
+        let (connection, mut events) = ManagedConnectionBuilder::default()
+            .with_url(url)
+            .with_credentials("key".to_string(), "secret".to_string())
+            .with_backoff(Duration::from_millis(10), Duration::from_millis(10))
+            .connect();
+
+        // a rejected key is a failed attempt, not a connection
+        let (tcp, _) = listener.accept().await.unwrap();
+        let mut ws = accept_async(tcp).await.unwrap();
+        assert_eq!(next_action(&mut ws).await, "authenticate");
+        answer(
+            &mut ws,
+            r#"{"action": "authenticate", "errorCode": 305, "error": "No active API key found."}"#,
+        )
+        .await;
+        assert!(matches!(
+            events.recv().await,
+            Some(ConnectionEvent::Event(Ok(BitvavoEvent::Error { .. })))
+        ));
+        assert!(matches!(
+            events.recv().await,
+            Some(ConnectionEvent::Reconnecting { attempt: 1, .. })
+        ));
+        assert!(connection.client().is_none());
+
+        let (tcp, _) = listener.accept().await.unwrap();
+        let mut ws = accept_async(tcp).await.unwrap();
+        assert_eq!(next_action(&mut ws).await, "authenticate");
+        answer(&mut ws, r#"{"event": "authenticate"}"#).await;
+        assert!(matches!(
+            events.recv().await,
+            Some(ConnectionEvent::Connected)
+        ));
+        assert!(matches!(
+            events.recv().await,
+            Some(ConnectionEvent::Event(Ok(BitvavoEvent::Authenticated)))
+        ));
+
+        connection
+            .subscribe(
+                SubscriptionBuilder::default()
+                    .with_market("BTC-EUR".to_string())
+                    .with_ticker()
+                    .build()
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(next_action(&mut ws).await, "subscribe");
+
+        // the exchange goes away
+        ws.close(None).await.unwrap();
+        drop(ws);
+        assert!(matches!(
+            events.recv().await,
+            Some(ConnectionEvent::Disconnected)
+        ));
+        assert!(matches!(
+            events.recv().await,
+            Some(ConnectionEvent::Reconnecting { attempt: 1, .. })
+        ));
+
+        let (tcp, _) = listener.accept().await.unwrap();
+        let mut ws = accept_async(tcp).await.unwrap();
+        assert_eq!(next_action(&mut ws).await, "authenticate");
+        answer(&mut ws, r#"{"event": "authenticate"}"#).await;
+        assert_eq!(next_action(&mut ws).await, "subscribe");
+        assert!(matches!(
+            events.recv().await,
+            Some(ConnectionEvent::Connected)
+        ));
+        assert!(connection.client().is_some());
+    }
+
+    #[tokio::test]
+    async fn dropping_the_events_closes_the_socket() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        let (_connection, mut events) = ManagedConnectionBuilder::default().with_url(url).connect();
+
+        let (tcp, _) = listener.accept().await.unwrap();
+        let mut ws = accept_async(tcp).await.unwrap();
+        assert!(matches!(
+            events.recv().await,
+            Some(ConnectionEvent::Connected)
+        ));
+
+        drop(events);
+        let closed = tokio::time::timeout(Duration::from_secs(5), ws.next())
+            .await
+            .expect("the socket stayed open");
+        assert!(matches!(
+            closed,
+            Some(Ok(tungstenite::Message::Close(_))) | None
+        ));
+    }
+}
@@ -0,0 +1,651 @@
+use serde::de::{self, Deserialize, Deserializer, Visitor};
+use serde::{Serialize, Serializer};
+use std::cmp::Ordering;
+use std::fmt;
+use std::fmt::{Debug, Display, Formatter};
+use std::hash::{Hash, Hasher};
+use std::iter::Sum;
+use std::ops::{Add, AddAssign, Div, Mul, Neg, Sub, SubAssign};
+use std::str::FromStr;
+
+// the digits kept after the point by divisions and by multiplications that
+// would otherwise need more
+pub const MAX_SCALE: u32 = 18;
+
+// an exact fixed-point number: `mantissa` * 10^-`scale`. The scale of a parsed
+// number is the number of digits after its point, trailing zeros included, so it
+// is formatted back the way the exchange sent it; comparisons and hashing only
+// look at the value, 100.50 == 100.5. Parsing keeps at most MAX_SCALE digits
+// after the point, so that sums of any realistic amounts fit
+#[derive(Clone, Copy, Default)]
+pub struct Decimal {
+    mantissa: i128,
+    scale: u32,
+}
+
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum Rounding {
+    // toward zero
+    Down,
+    // away from zero
+    Up,
+    // to the nearest, half away from zero
+    HalfUp,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ParseDecimalError {
+    Empty,
+    InvalidDigit(char),
+    TooManyDigits,
+}
+
+impl Display for ParseDecimalError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseDecimalError::Empty => write!(f, "no digits"),
+            ParseDecimalError::InvalidDigit(c) => write!(f, "invalid digit {:?}", c),
+            ParseDecimalError::TooManyDigits => write!(f, "too many digits"),
+        }
+    }
+}
+
+impl Decimal {
+    pub const ZERO: Decimal = Decimal {
+        mantissa: 0,
+        scale: 0,
+    };
+
+    pub const ONE: Decimal = Decimal {
+        mantissa: 1,
+        scale: 0,
+    };
+
+    pub fn new(mantissa: i128, scale: u32) -> Self {
+        Decimal { mantissa, scale }
+    }
+
+    pub fn mantissa(&self) -> i128 {
+        self.mantissa
+    }
+
+    pub fn scale(&self) -> u32 {
+        self.scale
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.mantissa == 0
+    }
+
+    pub fn is_sign_negative(&self) -> bool {
+        self.mantissa < 0
+    }
+
+    pub fn abs(&self) -> Self {
+        Decimal::new(self.mantissa.abs(), self.scale)
+    }
+
+    // the same value without trailing zeros after the point
+    pub fn normalize(&self) -> Self {
+        let mut normalized = *self;
+        while normalized.scale > 0 && normalized.mantissa % 10 == 0 {
+            normalized.mantissa /= 10;
+            normalized.scale -= 1;
+        }
+        normalized
+    }
+
+    // rounds half away from zero to at most `scale` digits after the point
+    pub fn round_dp(&self, scale: u32) -> Self {
+        self.round_dp_with(scale, Rounding::HalfUp)
+    }
+
+    pub fn round_dp_with(&self, scale: u32, rounding: Rounding) -> Self {
+        if self.scale <= scale {
+            return *self;
+        }
+        Decimal::new(
+            div_round(self.mantissa, self.scale - scale, rounding),
+            scale,
+        )
+    }
+
+    // rounds to at most `digits` significant digits, e.g. 12345.6 to 12346 or
+    // 1234567 to 1234600 with 5 of them
+    pub fn round_significant(&self, digits: u32, rounding: Rounding) -> Self {
+        let length = match self.mantissa.unsigned_abs().checked_ilog10() {
+            Some(log) => log + 1,
+            None => return *self,
+        };
+        if length <= digits {
+            return *self;
+        }
+        let dropped = length - digits;
+        if dropped <= self.scale {
+            return self.round_dp_with(self.scale - dropped, rounding);
+        }
+        let mantissa = div_round(self.mantissa, dropped, rounding)
+            .checked_mul(pow10(dropped - self.scale).expect("decimal overflow"))
+            .expect("decimal overflow");
+        Decimal::new(mantissa, 0)
+    }
+
+    // the same value with exactly `scale` digits after the point, rounding if needed
+    pub fn rescale(&self, scale: u32) -> Self {
+        if self.scale >= scale {
+            return self.round_dp(scale);
+        }
+        let mantissa = pow10(scale - self.scale)
+            .and_then(|factor| self.mantissa.checked_mul(factor))
+            .expect("decimal overflow");
+        Decimal::new(mantissa, scale)
+    }
+
+    pub fn checked_add(self, other: Decimal) -> Option<Decimal> {
+        let scale = self.scale.max(other.scale);
+        let mantissa = upscale(self, scale)?.checked_add(upscale(other, scale)?)?;
+        Some(Decimal::new(mantissa, scale))
+    }
+
+    pub fn checked_sub(self, other: Decimal) -> Option<Decimal> {
+        self.checked_add(-other)
+    }
+
+    // the product is rounded to MAX_SCALE digits; it is computed on 256 bits when
+    // the mantissas don't fit, as with two quotients
+    pub fn checked_mul(self, other: Decimal) -> Option<Decimal> {
+        let scale = self.scale + other.scale;
+        if let Some(mantissa) = self.mantissa.checked_mul(other.mantissa) {
+            return Some(Decimal::new(mantissa, scale).round_dp(MAX_SCALE));
+        }
+        let excess = scale.saturating_sub(MAX_SCALE);
+        let product = wide_mul(self.mantissa.unsigned_abs(), other.mantissa.unsigned_abs());
+        let magnitude = wide_div_round(product, 10u64.checked_pow(excess)?)?;
+        let mantissa = i128::try_from(magnitude).ok()?;
+        let negative = self.is_sign_negative() != other.is_sign_negative();
+        Some(Decimal::new(
+            if negative { -mantissa } else { mantissa },
+            scale - excess,
+        ))
+    }
+
+    // None when dividing by zero or on overflow; the quotient is rounded to
+    // MAX_SCALE digits
+    pub fn checked_div(self, other: Decimal) -> Option<Decimal> {
+        if other.is_zero() {
+            return None;
+        }
+        // long division of the mantissas, one digit at a time until the quotient is
+        // exact or has MAX_SCALE digits once the scales are accounted for, plus one
+        // to round with
+        let max_digits = (MAX_SCALE + other.scale).saturating_sub(self.scale) + 1;
+        let mut quotient = self.mantissa / other.mantissa;
+        let mut remainder = self.mantissa % other.mantissa;
+        let mut digits = 0;
+        while remainder != 0 && digits < max_digits {
+            remainder = remainder.checked_mul(10)?;
+            quotient = quotient
+                .checked_mul(10)?
+                .checked_add(remainder / other.mantissa)?;
+            remainder %= other.mantissa;
+            digits += 1;
+        }
+        let scale = (self.scale + digits) as i64 - other.scale as i64;
+        let quotient = if scale < 0 {
+            Decimal::new(quotient.checked_mul(pow10(-scale as u32)?)?, 0)
+        } else {
+            Decimal::new(quotient, scale as u32)
+        };
+        Some(quotient.round_dp(MAX_SCALE).normalize())
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        self.to_string().parse().unwrap_or(f64::NAN)
+    }
+}
+
+fn pow10(exponent: u32) -> Option<i128> {
+    10i128.checked_pow(exponent)
+}
+
+fn upscale(decimal: Decimal, scale: u32) -> Option<i128> {
+    decimal.mantissa.checked_mul(pow10(scale - decimal.scale)?)
+}
+
+// a * b as 64-bit limbs, the least significant first
+fn wide_mul(a: u128, b: u128) -> [u64; 4] {
+    let halves = |x: u128| [x as u64 as u128, x >> 64];
+    let mut limbs = [0u64; 4];
+    for (i, x) in halves(a).into_iter().enumerate() {
+        let mut carry = 0u128;
+        for (j, y) in halves(b).into_iter().enumerate() {
+            let t = x * y + limbs[i + j] as u128 + carry;
+            limbs[i + j] = t as u64;
+            carry = t >> 64;
+        }
+        limbs[i + 2] = carry as u64;
+    }
+    limbs
+}
+
+// limbs / divisor rounded half up, None if that doesn't fit in 128 bits
+fn wide_div_round(limbs: [u64; 4], divisor: u64) -> Option<u128> {
+    let divisor = divisor as u128;
+    let mut quotient = [0u64; 4];
+    let mut remainder = 0u128;
+    for i in (0..4).rev() {
+        let current = (remainder << 64) | limbs[i] as u128;
+        quotient[i] = (current / divisor) as u64;
+        remainder = current % divisor;
+    }
+    if quotient[2] != 0 || quotient[3] != 0 {
+        return None;
+    }
+    let quotient = (quotient[1] as u128) << 64 | quotient[0] as u128;
+    if remainder * 2 >= divisor {
+        quotient.checked_add(1)
+    } else {
+        Some(quotient)
+    }
+}
+
+// mantissa / 10^digits, rounded
+fn div_round(mantissa: i128, digits: u32, rounding: Rounding) -> i128 {
+    // beyond 10^38 the divisor exceeds any mantissa
+    let Some(divisor) = pow10(digits) else {
+        return match rounding {
+            Rounding::Up if mantissa != 0 => mantissa.signum(),
+            _ => 0,
+        };
+    };
+    let quotient = mantissa / divisor;
+    let remainder = mantissa % divisor;
+    let away = match rounding {
+        Rounding::Down => false,
+        Rounding::Up => remainder != 0,
+        Rounding::HalfUp => remainder.abs() >= divisor - remainder.abs(),
+    };
+    if away {
+        quotient + mantissa.signum()
+    } else {
+        quotient
+    }
+}
+
+impl FromStr for Decimal {
+    type Err = ParseDecimalError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (negative, digits) = match s.strip_prefix('-') {
+            Some(digits) => (true, digits),
+            None => (false, s),
+        };
+        let (integer, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+        if integer.is_empty() && fraction.is_empty() {
+            return Err(ParseDecimalError::Empty);
+        }
+
+        let mut mantissa: i128 = 0;
+        for c in integer.chars().chain(fraction.chars()) {
+            let digit = c.to_digit(10).ok_or(ParseDecimalError::InvalidDigit(c))?;
+            mantissa = mantissa
+                .checked_mul(10)
+                .and_then(|m| m.checked_add(digit as i128))
+                .ok_or(ParseDecimalError::TooManyDigits)?;
+        }
+        let mut scale = fraction.len() as u32;
+        // zeros beyond MAX_SCALE go, any other digit there is too precise
+        while scale > MAX_SCALE && mantissa % 10 == 0 {
+            mantissa /= 10;
+            scale -= 1;
+        }
+        if scale > MAX_SCALE {
+            return Err(ParseDecimalError::TooManyDigits);
+        }
+        Ok(Decimal::new(
+            if negative { -mantissa } else { mantissa },
+            scale,
+        ))
+    }
+}
+
+impl Display for Decimal {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let digits = self.mantissa.unsigned_abs().to_string();
+        let sign = if self.mantissa < 0 { "-" } else { "" };
+        let scale = self.scale as usize;
+        if scale == 0 {
+            return write!(f, "{}{}", sign, digits);
+        }
+        let digits = format!("{:0>width$}", digits, width = scale + 1);
+        let (integer, fraction) = digits.split_at(digits.len() - scale);
+        write!(f, "{}{}.{}", sign, integer, fraction)
+    }
+}
+
+impl Debug for Decimal {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        Display::fmt(self, f)
+    }
+}
+
+impl PartialEq for Decimal {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Decimal {}
+
+impl PartialOrd for Decimal {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Decimal {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let scale = self.scale.max(other.scale);
+        if let (Some(a), Some(b)) = (upscale(*self, scale), upscale(*other, scale)) {
+            return a.cmp(&b);
+        }
+        // trailing zeros can take a value out of range at the common scale, like
+        // hashing this only looks at the normalized values
+        let (a, b) = (self.normalize(), other.normalize());
+        let scale = a.scale.max(b.scale);
+        match (upscale(a, scale), upscale(b, scale)) {
+            (Some(x), Some(y)) => x.cmp(&y),
+            // whatever doesn't fit at the common scale is the larger one in magnitude
+            (None, _) if a.mantissa > 0 => Ordering::Greater,
+            (None, _) => Ordering::Less,
+            (_, None) if b.mantissa > 0 => Ordering::Less,
+            (_, None) => Ordering::Greater,
+        }
+    }
+}
+
+impl Hash for Decimal {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        let normalized = self.normalize();
+        normalized.mantissa.hash(state);
+        normalized.scale.hash(state);
+    }
+}
+
+macro_rules! from_integer {
+    ($($t:ty),*) => {
+        $(
+            impl From<$t> for Decimal {
+                fn from(value: $t) -> Self {
+                    Decimal::new(value as i128, 0)
+                }
+            }
+        )*
+    };
+}
+
+from_integer!(i8, i16, i32, i64, u8, u16, u32, u64);
+
+impl Neg for Decimal {
+    type Output = Decimal;
+
+    fn neg(self) -> Decimal {
+        Decimal::new(-self.mantissa, self.scale)
+    }
+}
+
+// the operators panic on overflow and on division by zero, like the integer ones
+macro_rules! operator {
+    ($trait:ident, $method:ident, $checked:ident, $message:literal) => {
+        impl $trait for Decimal {
+            type Output = Decimal;
+
+            fn $method(self, other: Decimal) -> Decimal {
+                self.$checked(other).expect($message)
+            }
+        }
+
+        impl $trait<&Decimal> for Decimal {
+            type Output = Decimal;
+
+            fn $method(self, other: &Decimal) -> Decimal {
+                self.$checked(*other).expect($message)
+            }
+        }
+
+        impl $trait<Decimal> for &Decimal {
+            type Output = Decimal;
+
+            fn $method(self, other: Decimal) -> Decimal {
+                (*self).$checked(other).expect($message)
+            }
+        }
+
+        impl $trait<&Decimal> for &Decimal {
+            type Output = Decimal;
+
+            fn $method(self, other: &Decimal) -> Decimal {
+                (*self).$checked(*other).expect($message)
+            }
+        }
+    };
+}
+
+operator!(Add, add, checked_add, "decimal overflow");
+operator!(Sub, sub, checked_sub, "decimal overflow");
+operator!(Mul, mul, checked_mul, "decimal overflow");
+operator!(
+    Div,
+    div,
+    checked_div,
+    "decimal division by zero or overflow"
+);
+
+impl AddAssign for Decimal {
+    fn add_assign(&mut self, other: Decimal) {
+        *self = *self + other;
+    }
+}
+
+impl SubAssign for Decimal {
+    fn sub_assign(&mut self, other: Decimal) {
+        *self = *self - other;
+    }
+}
+
+impl Sum for Decimal {
+    fn sum<I: Iterator<Item = Decimal>>(iter: I) -> Self {
+        iter.fold(Decimal::ZERO, Add::add)
+    }
+}
+
+impl<'a> Sum<&'a Decimal> for Decimal {
+    fn sum<I: Iterator<Item = &'a Decimal>>(iter: I) -> Self {
+        iter.fold(Decimal::ZERO, Add::add)
+    }
+}
+
+impl Serialize for Decimal {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Decimal {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct DecimalVisitor;
+
+        impl Visitor<'_> for DecimalVisitor {
+            type Value = Decimal;
+
+            fn expecting(&self, formatter: &mut Formatter) -> fmt::Result {
+                formatter.write_str("a JSON string representing a decimal number")
+            }
+
+            fn visit_str<E>(self, value: &str) -> Result<Self::Value, E>
+            where
+                E: de::Error,
+            {
+                value.parse().map_err(|err| {
+                    de::Error::custom(format!("failed to parse decimal {:?}: {}", value, err))
+                })
+            }
+
+            fn visit_u64<E>(self, value: u64) -> Result<Self::Value, E>
+            where
+                E: de::Error,
+            {
+                Ok(Decimal::from(value))
+            }
+
+            fn visit_i64<E>(self, value: i64) -> Result<Self::Value, E>
+            where
+                E: de::Error,
+            {
+                Ok(Decimal::from(value))
+            }
+        }
+
+        deserializer.deserialize_any(DecimalVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    fn d(s: &str) -> Decimal {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn parse_and_format() {
+        for s in [
+            "0",
+            "100.50",
+            "0.00000001",
+            "-0.5",
+            "123456789.123456789",
+            "7.",
+        ] {
+            assert_eq!(d(s).to_string(), s.trim_end_matches('.'), "{}", s);
+        }
+        assert_eq!(d(".5").to_string(), "0.5");
+        assert_eq!("".parse::<Decimal>(), Err(ParseDecimalError::Empty));
+        assert_eq!(
+            "1e-8".parse::<Decimal>(),
+            Err(ParseDecimalError::InvalidDigit('e'))
+        );
+        assert_eq!(
+            "1".repeat(40).parse::<Decimal>(),
+            Err(ParseDecimalError::TooManyDigits)
+        );
+
+        // more digits after the point than sums can line up
+        assert_eq!(
+            "0.000000000000000000000000000001".parse::<Decimal>(),
+            Err(ParseDecimalError::TooManyDigits)
+        );
+        let zeros = d(&format!("1.5{}", "0".repeat(30)));
+        assert_eq!(zeros.scale(), MAX_SCALE);
+        assert_eq!(zeros, d("1.5"));
+        let smallest = d("0.000000000000000001");
+        assert_eq!(
+            (smallest + d("1000000000")).to_string(),
+            "1000000000.000000000000000001"
+        );
+        assert_eq!(
+            d("-100000000000000000000") - smallest,
+            d("-100000000000000000000.000000000000000001")
+        );
+    }
+
+    #[test]
+    fn compare_by_value() {
+        assert_eq!(d("100.50"), d("100.5"));
+        assert!(d("0.1") < d("0.11"));
+        assert!(d("-1") < d("0.001"));
+        assert!(Decimal::new(i128::MAX, 0) > Decimal::new(1, 21));
+
+        let set: HashSet<Decimal> = [d("1.10"), d("1.1"), d("1.100")].into_iter().collect();
+        assert_eq!(set.len(), 1);
+
+        // equal values hash the same, however many zeros they carry
+        let zeros = Decimal::new(0, 40);
+        assert_eq!(zeros, Decimal::ZERO);
+        assert_eq!(Decimal::new(5 * 10i128.pow(36), 36), d("5"));
+        assert!(Decimal::new(1, 31) < Decimal::new(i128::MAX, 0));
+        let set: HashSet<Decimal> = [zeros, Decimal::ZERO].into_iter().collect();
+        assert_eq!(set.len(), 1);
+    }
+
+    #[test]
+    fn arithmetic() {
+        assert_eq!((d("0.1") + d("0.2")).to_string(), "0.3");
+        assert_eq!((d("100") - d("100.25")).to_string(), "-0.25");
+        assert_eq!((d("0.50") * d("2")).to_string(), "1.00");
+        assert_eq!((d("1") / d("4")).to_string(), "0.25");
+        assert_eq!((d("2") / d("3")).to_string(), "0.666666666666666667");
+        assert_eq!((d("-1") / d("3")).to_string(), "-0.333333333333333333");
+        assert_eq!((d("5000") / d("0.0002")).to_string(), "25000000");
+        assert_eq!(d("1").checked_div(Decimal::ZERO), None);
+        assert_eq!(
+            [d("1.5"), d("2.25"), d("-0.75")].iter().sum::<Decimal>(),
+            d("3")
+        );
+        assert_eq!(d("2.345").round_dp(2).to_string(), "2.35");
+        assert_eq!(d("-2.345").round_dp(2).to_string(), "-2.35");
+        assert_eq!(d("2.5").rescale(3).to_string(), "2.500");
+    }
+
+    #[test]
+    fn multiply_quotients() {
+        // exact products of the rounded quotients, rounded to MAX_SCALE
+        let third = d("40000") / d("3");
+        assert_eq!((third * third).to_string(), "177777777.777777777777768889");
+        let seventh = d("40000.12") / d("7");
+        assert_eq!(seventh * seventh, d("32653257.143151020408161633"));
+        assert_eq!(-seventh * seventh, d("-32653257.143151020408161633"));
+        assert_eq!(Decimal::new(i128::MAX, 0).checked_mul(d("2")), None);
+    }
+
+    #[test]
+    fn rounding() {
+        assert_eq!(
+            d("1.239").round_dp_with(2, Rounding::Down).to_string(),
+            "1.23"
+        );
+        assert_eq!(
+            d("1.231").round_dp_with(2, Rounding::Up).to_string(),
+            "1.24"
+        );
+        assert_eq!(
+            d("-1.231").round_dp_with(2, Rounding::Up).to_string(),
+            "-1.24"
+        );
+
+        let significant = |s, rounding| d(s).round_significant(5, rounding).to_string();
+        assert_eq!(significant("12345.6", Rounding::HalfUp), "12346");
+        assert_eq!(significant("1234567", Rounding::Down), "1234500");
+        assert_eq!(significant("0.000123456", Rounding::Up), "0.00012346");
+        assert_eq!(significant("99999.9", Rounding::Up), "100000");
+        assert_eq!(significant("1.5", Rounding::Down), "1.5");
+    }
+
+    #[test]
+    fn serde_round_trip() {
+        let json = r#"["100.50","0.00010000","-3"]"#;
+        let decimals: Vec<Decimal> = serde_json::from_str(json).unwrap();
+        assert_eq!(serde_json::to_string(&decimals).unwrap(), json);
+
+        let number: Decimal = serde_json::from_str("42").unwrap();
+        assert_eq!(number, d("42"));
+    }
+}
@@ -1,14 +1,18 @@
-use crate::candle::{Candle, CandleEvent};
+use crate::candle::{CandleEvent, CandlesResponse};
 use crate::event::{
-    BitvavoEvent, BookResponse, GetBalancesResponse, SubscriptionResponse, Ticker, Ticker24h,
-    TickerBookResponse,
+    AccountResponse, AccountTradesResponse, AssetsResponse, BitvavoEvent, BookResponse,
+    CancelOrderResponse, CancelOrdersResponse, DepositAssetsResponse, DepositHistoryResponse,
+    ErrorResponse, Fill, GetBalancesResponse, GetOrderResponse, GetOrdersResponse, Order,
+    PlaceOrderResponse, PublicTradesResponse, Ticker, Ticker24hEvent, Ticker24hResponse,
+    TickerBookResponse, TickerPriceResponse, TimeResponse, UpdateOrderResponse,
+    WithdrawalHistoryResponse,
 };
 use crate::market::MarketsResponse;
 use crate::price_level::Book;
 use std::collections::HashMap;
 
 use crate::trade::Trade;
-use serde_json::{from_value, Error};
+use serde_json::{Error, from_value};
 use std::fmt::{Debug, Formatter};
 
 pub enum DecodeError {
@@ -36,14 +40,49 @@ impl From<serde_json::Error> for DecodeError {
 }
 
 pub fn decode_event(message: &str) -> Result<BitvavoEvent, DecodeError> {
+    decode_response(message).1
+}
+
+// same as decode_event, but also hands back the `requestId` the exchange echoed
+// so that the response can be matched with the action that caused it
+pub fn decode_response(message: &str) -> (Option<u64>, Result<BitvavoEvent, DecodeError>) {
     let message_str = message.to_string();
 
     if !message_str.starts_with("{") {
         log::error!("message is weird: {}", message_str);
-        return Err(DecodeError::NonDecodeableMessage(message_str.to_string()));
+        return (
+            None,
+            Err(DecodeError::NonDecodeableMessage(message_str.to_string())),
+        );
+    }
+
+    let value: serde_json::Value = match serde_json::from_str(message_str.as_str()) {
+        Ok(value) => value,
+        Err(e) => return (None, Err(e.into())),
+    };
+    let request_id = value.get("requestId").and_then(|v| v.as_u64());
+
+    (request_id, decode_value(value, &message_str))
+}
+
+fn decode_value(value: serde_json::Value, message_str: &str) -> Result<BitvavoEvent, DecodeError> {
+    // errors, whatever action or event they are about
+    if value.get("errorCode").is_some() {
+        let error_response = from_value::<ErrorResponse>(value)?;
+        log::error!(
+            "exchange error {} for {:?}: {}",
+            error_response.error_code,
+            error_response.action,
+            error_response.error
+        );
+        return Ok(BitvavoEvent::Error {
+            action: error_response.action,
+            error_code: error_response.error_code,
+            error: error_response.error,
+            request_id: error_response.request_id,
+        });
     }
 
-    let value: serde_json::Value = serde_json::from_str(message_str.as_str())?;
     let maybe_event_type = value.get("event").and_then(|v| v.as_str());
 
     // events
@@ -51,6 +90,7 @@ pub fn decode_event(message: &str) -> Result<BitvavoEvent, DecodeError> {
         return match event_type {
             "authenticate" => Ok(BitvavoEvent::Authenticated),
             "subscribed" => Ok(BitvavoEvent::Subscribed),
+            "unsubscribed" => Ok(BitvavoEvent::Unsubscribed),
 
             "book" => {
                 let book = from_value::<Book>(value)?;
@@ -58,8 +98,10 @@ pub fn decode_event(message: &str) -> Result<BitvavoEvent, DecodeError> {
             }
 
             "candle" => {
-                let candle_response = from_value::<CandleEvent>(value)?;
-                let candle = Candle::from_serde_array(candle_response.candle.first().unwrap());
+                let candle = from_value::<CandleEvent>(value)?;
+                if candle.candles.is_empty() {
+                    return Err(DecodeError::NonDecodeableMessage(message_str.to_string()));
+                }
                 Ok(BitvavoEvent::from_candle(candle))
             }
 
@@ -68,23 +110,33 @@ pub fn decode_event(message: &str) -> Result<BitvavoEvent, DecodeError> {
                 Ok(BitvavoEvent::from_trade(trade))
             }
 
+            "order" => {
+                let order = from_value::<Order>(value)?;
+                Ok(BitvavoEvent::OrderUpdate(Box::new(order)))
+            }
+
+            "fill" => {
+                let fill = from_value::<Fill>(value)?;
+                Ok(BitvavoEvent::Fill(fill))
+            }
+
             "ticker" => {
                 let ticker = from_value::<Ticker>(value);
                 match ticker {
                     Ok(ticker) => Ok(BitvavoEvent::from_ticker(ticker)),
                     Err(e) => {
-                        log::error!("error: {:?}, payload = {}", &e, &message_str);
+                        log::error!("error: {:?}, payload = {}", &e, message_str);
                         Err(DecodeError::NonParseableMessage(e))
                     }
                 }
             }
 
             "ticker24h" => {
-                let ticker = from_value::<Ticker24h>(value);
+                let ticker = from_value::<Ticker24hEvent>(value);
                 match ticker {
-                    Ok(ticker) => Ok(BitvavoEvent::from_ticker24h(ticker)),
+                    Ok(ticker) => Ok(BitvavoEvent::from_ticker24h(ticker.data)),
                     Err(e) => {
-                        log::error!("error: {:?}, payload = {}", &e, &message_str);
+                        log::error!("error: {:?}, payload = {}", &e, message_str);
                         Err(DecodeError::UnknownEvent(e.to_string()))
                     }
                 }
@@ -97,7 +149,7 @@ pub fn decode_event(message: &str) -> Result<BitvavoEvent, DecodeError> {
         };
     }
 
-    let maybe_action_type = value.get("action").unwrap().as_str();
+    let maybe_action_type = value.get("action").and_then(|v| v.as_str());
 
     // actions
     if let Some(action_type) = maybe_action_type {
@@ -106,6 +158,32 @@ pub fn decode_event(message: &str) -> Result<BitvavoEvent, DecodeError> {
                 from_value::<MarketsResponse>(value)?.response,
             )),
 
+            "getTime" => Ok(BitvavoEvent::Time(
+                from_value::<TimeResponse>(value)?.response.time,
+            )),
+
+            "getAssets" => Ok(BitvavoEvent::Assets(
+                from_value::<AssetsResponse>(value)?.response.into_vec(),
+            )),
+
+            "getTicker24h" => Ok(BitvavoEvent::Ticker24h(
+                from_value::<Ticker24hResponse>(value)?.response.into_vec(),
+            )),
+
+            "getTickerPrice" => Ok(BitvavoEvent::TickerPrices(
+                from_value::<TickerPriceResponse>(value)?
+                    .response
+                    .into_vec(),
+            )),
+
+            "getTrades" => Ok(BitvavoEvent::Trades(
+                from_value::<PublicTradesResponse>(value)?.response,
+            )),
+
+            "getCandles" => Ok(BitvavoEvent::Candles(
+                from_value::<CandlesResponse>(value)?.response,
+            )),
+
             "getTickerBook" => Ok(BitvavoEvent::TickerBook(from_value::<TickerBookResponse>(
                 value,
             )?)),
@@ -120,18 +198,60 @@ pub fn decode_event(message: &str) -> Result<BitvavoEvent, DecodeError> {
                 Ok(BitvavoEvent::Balances(balances))
             }
 
+            "privateGetTrades" => Ok(BitvavoEvent::AccountTrades(
+                from_value::<AccountTradesResponse>(value)?.response,
+            )),
+
+            "privateGetAccount" => Ok(BitvavoEvent::Account(
+                from_value::<AccountResponse>(value)?.response,
+            )),
+
+            "privateGetDepositHistory" => Ok(BitvavoEvent::Deposits(
+                from_value::<DepositHistoryResponse>(value)?.response,
+            )),
+
+            "privateGetWithdrawalHistory" => Ok(BitvavoEvent::Withdrawals(
+                from_value::<WithdrawalHistoryResponse>(value)?.response,
+            )),
+
+            "privateDepositAssets" => Ok(BitvavoEvent::DepositAddress(
+                from_value::<DepositAssetsResponse>(value)?.response,
+            )),
+
             "getBook" => {
                 let book_response = from_value::<BookResponse>(value)?;
-                Ok(BitvavoEvent::Book(book_response.response))
+                Ok(BitvavoEvent::BookSnapshot(book_response.response))
             }
 
-            "subscribe" => {
-                let s_response = from_value::<SubscriptionResponse>(value)?;
-                if let Some(e) = s_response.error {
-                    panic!("subscription confirmation failed: {:?}", e);
-                }
-                Ok(BitvavoEvent::Subscribed)
-            }
+            "placeOrder" | "privateCreateOrder" => Ok(BitvavoEvent::PlacedOrder(Box::new(
+                from_value::<PlaceOrderResponse>(value)?.response,
+            ))),
+
+            "updateOrder" | "privateUpdateOrder" => Ok(BitvavoEvent::UpdatedOrder(Box::new(
+                from_value::<UpdateOrderResponse>(value)?.response,
+            ))),
+
+            "getOrder" | "privateGetOrder" => Ok(BitvavoEvent::Order(Box::new(
+                from_value::<GetOrderResponse>(value)?.response,
+            ))),
+
+            "getOrders" | "privateGetOrders" | "getOrdersOpen" | "privateGetOrdersOpen" => Ok(
+                BitvavoEvent::Orders(from_value::<GetOrdersResponse>(value)?.response),
+            ),
+
+            "cancelOrder" | "privateCancelOrder" => Ok(BitvavoEvent::CancelledOrder(
+                from_value::<CancelOrderResponse>(value)?.response.order_id,
+            )),
+
+            "cancelOrders" | "privateCancelOrders" => Ok(BitvavoEvent::CancelledOrders(
+                from_value::<CancelOrdersResponse>(value)?
+                    .response
+                    .into_iter()
+                    .map(|cancelled| cancelled.order_id)
+                    .collect(),
+            )),
+
+            "subscribe" => Ok(BitvavoEvent::Subscribed),
 
             action_type => {
                 log::debug!("Unknown action type: {}", action_type);
@@ -140,6 +260,195 @@ pub fn decode_event(message: &str) -> Result<BitvavoEvent, DecodeError> {
         };
     }
 
-    // wtf
-    panic!("{:?}", &value);
+    // neither an event nor an action
+    log::error!("message is weird: {}", message_str);
+    Err(DecodeError::NonDecodeableMessage(message_str.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::candle::Interval;
+    use crate::error_code::BitvavoErrorCode;
+    use crate::order::{OrderStatus, TimeInForce, TriggerReference};
+    use crate::side::Side;
+
+    #[test]
+    fn decode_error_response() {
+        let message = r#"{"action": "privateCreateOrder", "requestId": 3, "errorCode": 216, "error": "You do not have sufficient balance to complete this operation."}"#;
+        let (request_id, event) = decode_response(message);
+        assert_eq!(request_id, Some(3));
+        match event {
+            Ok(BitvavoEvent::Error {
+                action,
+                error_code,
+                request_id,
+                ..
+            }) => {
+                assert_eq!(action.as_deref(), Some("privateCreateOrder"));
+                assert_eq!(error_code, BitvavoErrorCode::InsufficientBalance);
+                assert_eq!(request_id, Some(3));
+            }
+            other => panic!("unexpected: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn decode_subscription_error() {
+        let message = r#"{"action": "subscribe", "errorCode": 205, "error": "Invalid parameter"}"#;
+        assert!(matches!(
+            decode_event(message),
+            Ok(BitvavoEvent::Error {
+                error_code: BitvavoErrorCode::InvalidParameter,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn decode_account_events() {
+        let order = r#"{"event": "order", "orderId": "80b5f04d", "market": "BTC-EUR", "created": 1548684420771, "updated": 1548684420771, "status": "partiallyFilled", "side": "buy", "orderType": "limit", "amount": "1.5", "amountRemaining": "0.5", "price": "4000", "onHold": "4004", "onHoldCurrency": "EUR", "selfTradePrevention": "decrementAndCancel", "visible": true, "timeInForce": "GTC", "postOnly": false}"#;
+        match decode_event(order) {
+            Ok(BitvavoEvent::OrderUpdate(order)) => {
+                assert_eq!(order.order_id, "80b5f04d");
+                assert_eq!(order.status, OrderStatus::PartiallyFilled);
+                assert_eq!(order.side, Side::Buy);
+                assert_eq!(order.amount_remaining.unwrap().float, 0.5);
+                assert_eq!(order.time_in_force, Some(TimeInForce::GoodTillCancelled));
+                assert!(order.fills.is_empty());
+            }
+            other => panic!("unexpected: {:?}", other),
+        }
+
+        let fill = r#"{"event": "fill", "market": "BTC-EUR", "orderId": "80b5f04d", "fillId": "b1f1", "timestamp": 1548684420800, "amount": "1", "side": "buy", "price": "4000", "taker": true, "fee": "10", "feeCurrency": "EUR"}"#;
+        match decode_event(fill) {
+            Ok(BitvavoEvent::Fill(fill)) => {
+                assert_eq!(fill.id, "b1f1");
+                assert_eq!(fill.order_id.as_deref(), Some("80b5f04d"));
+                assert_eq!(fill.side, Some(Side::Buy));
+                assert_eq!(fill.price.float, 4000);
+                assert!(fill.taker);
+            }
+            other => panic!("unexpected: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn decode_orders_response() {
+        let message = r#"{"action": "privateGetOrdersOpen", "requestId": 9, "response": [{"orderId": "1", "market": "BTC-EUR", "created": 1, "updated": 2, "status": "new", "side": "sell", "orderType": "limit", "amount": "1", "price": "50000"}, {"orderId": "2", "market": "ETH-EUR", "created": 1, "updated": 1, "status": "awaitingTrigger", "side": "buy", "orderType": "stopLoss", "amountQuote": "100", "triggerAmount": "2000", "triggerType": "price", "triggerReference": "bestAsk"}]}"#;
+        let (request_id, event) = decode_response(message);
+        assert_eq!(request_id, Some(9));
+        match event {
+            Ok(BitvavoEvent::Orders(orders)) => {
+                assert_eq!(orders.len(), 2);
+                assert!(orders.iter().all(|order| order.status.is_open()));
+                assert_eq!(orders[1].trigger_reference, Some(TriggerReference::BestAsk));
+            }
+            other => panic!("unexpected: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn decode_market_data_responses() {
+        let message = r#"{"action": "getTime", "response": {"time": 1700000000123}}"#;
+        assert!(matches!(
+            decode_event(message),
+            Ok(BitvavoEvent::Time(1700000000123))
+        ));
+
+        let message =
+            r#"{"action": "getTickerPrice", "response": {"market": "BTC-EUR", "price": "40000"}}"#;
+        match decode_event(message) {
+            Ok(BitvavoEvent::TickerPrices(prices)) => {
+                assert_eq!(prices.len(), 1);
+                assert_eq!(prices[0].price.as_ref().unwrap().float, 40000);
+            }
+            other => panic!("unexpected: {:?}", other),
+        }
+
+        let message = r#"{"action": "getCandles", "response": [[1700000060000, "2", "3", "1", "2.5", "10"], [1700000000000, "1", "2", "1", "2", "5"]]}"#;
+        match decode_event(message) {
+            Ok(BitvavoEvent::Candles(candles)) => {
+                assert_eq!(candles.len(), 2);
+                assert_eq!(candles[1].timestamp, 1700000000000);
+            }
+            other => panic!("unexpected: {:?}", other),
+        }
+
+        let message = r#"{"event": "candle", "market": "BTC-EUR", "interval": "1h", "candle": [[1700003600000, "2", "3", "1", "2.5", "10"], [1700000000000, "1", "2", "1", "2", "5"]]}"#;
+        match decode_event(message) {
+            Ok(BitvavoEvent::Candle(event)) => {
+                assert_eq!(event.market, "BTC-EUR");
+                assert_eq!(event.interval, Interval::OneHour);
+                assert_eq!(event.candles.len(), 2);
+                assert_eq!(event.candles[0].close, "2.5".parse().unwrap());
+            }
+            other => panic!("unexpected: {:?}", other),
+        }
+
+        let message = r#"{"event": "ticker24h", "data": [{"market": "BTC-EUR", "open": "39000", "high": "41000", "low": "38000", "last": "40000", "volume": "12", "volumeQuote": "480000", "bid": "39990", "bidSize": "1", "ask": "40010", "askSize": "2", "timestamp": 1700000000000}, {"market": "XYZ-EUR", "open": null, "high": null, "low": null, "last": null, "volume": null, "volumeQuote": null, "bid": null, "bidSize": null, "ask": null, "askSize": null, "timestamp": 1700000000000}]}"#;
+        match decode_event(message) {
+            Ok(BitvavoEvent::Ticker24h(tickers)) => {
+                assert_eq!(tickers.len(), 2);
+                assert!(tickers[1].last.is_none());
+            }
+            other => panic!("unexpected: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn decode_account_history_responses() {
+        let message = r#"{"action": "privateGetTrades", "response": [{"id": "108c3633", "orderId": "1d671998", "timestamp": 1542967486256, "market": "BTC-EUR", "side": "buy", "amount": "0.005", "price": "5000.1", "taker": true, "fee": "0.03", "feeCurrency": "EUR", "settled": true}]}"#;
+        match decode_event(message) {
+            Ok(BitvavoEvent::AccountTrades(trades)) => {
+                assert_eq!(trades[0].order_id, "1d671998");
+                assert_eq!(trades[0].fee.str_repr, "0.03");
+            }
+            other => panic!("unexpected: {:?}", other),
+        }
+
+        let message = r#"{"action": "privateGetAccount", "response": {"fees": {"tier": 0, "volume": "10000.00", "maker": "0.0015", "taker": "0.0025"}, "capabilities": ["buy", "sell"]}}"#;
+        match decode_event(message) {
+            Ok(BitvavoEvent::Account(account)) => {
+                assert_eq!(account.fees.tier, Some(0));
+                assert_eq!(account.fees.taker.str_repr, "0.0025");
+            }
+            other => panic!("unexpected: {:?}", other),
+        }
+
+        let message = r#"{"action": "privateGetWithdrawalHistory", "response": [{"timestamp": 1542967486256, "symbol": "BTC", "amount": "0.99994", "fee": "0.00006", "status": "awaiting_processing", "address": "BitcoinAddress", "txId": "927b3ea5"}]}"#;
+        match decode_event(message) {
+            Ok(BitvavoEvent::Withdrawals(withdrawals)) => {
+                assert_eq!(withdrawals[0].tx_id.as_deref(), Some("927b3ea5"));
+                assert!(withdrawals[0].payment_id.is_none());
+            }
+            other => panic!("unexpected: {:?}", other),
+        }
+
+        let message = r#"{"action": "privateDepositAssets", "response": {"iban": "NL32BUNQ2291234129", "bic": "BUNQNL2A", "description": "254D20CC94"}}"#;
+        match decode_event(message) {
+            Ok(BitvavoEvent::DepositAddress(address)) => {
+                assert!(address.address.is_none());
+                assert_eq!(address.bic.as_deref(), Some("BUNQNL2A"));
+            }
+            other => panic!("unexpected: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn decode_bad_input_without_panicking() {
+        for message in [
+            "",
+            "not json",
+            "{",
+            "{}",
+            r#"{"action": 42}"#,
+            r#"{"event": "candle", "market": "BTC-EUR", "interval": "1m", "candle": []}"#,
+            r#"{"event": "candle", "market": "BTC-EUR", "interval": "1m", "candle": [[1, 2]]}"#,
+            r#"{"event": "candle", "market": "BTC-EUR", "interval": "3m", "candle": [[1, "1", "1", "1", "1", "1"]]}"#,
+            r#"{"errorCode": "oops"}"#,
+        ] {
+            assert!(decode_event(message).is_err(), "{}", message);
+        }
+    }
 }
@@ -0,0 +1,208 @@
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+
+// the `errorCode`s documented by the exchange, anything else is kept as Unknown
+#[derive(Debug, Eq, PartialEq, Clone, Copy, Hash, Serialize, Deserialize)]
+#[serde(from = "u32", into = "u32")]
+pub enum BitvavoErrorCode {
+    UnknownError,
+    InvalidJson,
+    RateLimited,
+    OrderRateLimited,
+    Banned,
+    MatchingEngineOverloaded,
+    MatchingEngineTimeout,
+    MatchingEngineNoResponse,
+    InvalidEndpoint,
+    MissingOrIncompatibleParameters,
+    ParameterNotSupportedForOrderType,
+    InvalidParameterForEndpoint,
+    InvalidParameter,
+    AmountAndAmountQuote,
+    AmountTooHigh,
+    PriceTooHigh,
+    AmountTooLow,
+    PriceTooLow,
+    PriceTooDetailed,
+    InsufficientBalance,
+    MinimumQuoteOrderSizeNotMet,
+    MinimumBaseOrderSizeNotMet,
+    MarketInactive,
+    MarketNotAllowed,
+    TimeInForceNotAllowed,
+    NothingToUpdate,
+    OrderNotActive,
+    MarketOrderNotUpdatable,
+    TooManyOpenOrders,
+    AmountAndAmountRemaining,
+    OrderNotFound,
+    AuthenticationRequired,
+    InvalidApiKeyLength,
+    InvalidTimestamp,
+    InvalidWindow,
+    OutsideAcceptanceWindow,
+    NoActiveApiKey,
+    ApiKeyNotConfirmed,
+    IpNotAllowed,
+    InvalidSignatureLength,
+    InvalidSignature,
+    TradingNotAllowed,
+    AccountInfoNotAllowed,
+    WithdrawalNotAllowed,
+    AccountLocked,
+    DepositsUnavailable,
+    WithdrawalBelowMinimum,
+    WithdrawalInsufficientBalance,
+    WithdrawalsUnavailable,
+    Unknown(u32),
+}
+
+impl BitvavoErrorCode {
+    pub fn code(&self) -> u32 {
+        match self {
+            BitvavoErrorCode::UnknownError => 101,
+            BitvavoErrorCode::InvalidJson => 102,
+            BitvavoErrorCode::RateLimited => 103,
+            BitvavoErrorCode::OrderRateLimited => 104,
+            BitvavoErrorCode::Banned => 105,
+            BitvavoErrorCode::MatchingEngineOverloaded => 107,
+            BitvavoErrorCode::MatchingEngineTimeout => 108,
+            BitvavoErrorCode::MatchingEngineNoResponse => 109,
+            BitvavoErrorCode::InvalidEndpoint => 110,
+            BitvavoErrorCode::ParameterNotSupportedForOrderType => 202,
+            BitvavoErrorCode::MissingOrIncompatibleParameters => 203,
+            BitvavoErrorCode::InvalidParameterForEndpoint => 204,
+            BitvavoErrorCode::InvalidParameter => 205,
+            BitvavoErrorCode::AmountAndAmountQuote => 206,
+            BitvavoErrorCode::AmountTooHigh => 210,
+            BitvavoErrorCode::PriceTooHigh => 211,
+            BitvavoErrorCode::AmountTooLow => 212,
+            BitvavoErrorCode::PriceTooLow => 213,
+            BitvavoErrorCode::PriceTooDetailed => 214,
+            BitvavoErrorCode::InsufficientBalance => 216,
+            BitvavoErrorCode::MinimumQuoteOrderSizeNotMet => 217,
+            BitvavoErrorCode::MinimumBaseOrderSizeNotMet => 218,
+            BitvavoErrorCode::MarketInactive => 219,
+            BitvavoErrorCode::MarketNotAllowed => 220,
+            BitvavoErrorCode::TimeInForceNotAllowed => 231,
+            BitvavoErrorCode::NothingToUpdate => 232,
+            BitvavoErrorCode::OrderNotActive => 233,
+            BitvavoErrorCode::MarketOrderNotUpdatable => 234,
+            BitvavoErrorCode::TooManyOpenOrders => 235,
+            BitvavoErrorCode::AmountAndAmountRemaining => 236,
+            BitvavoErrorCode::OrderNotFound => 240,
+            BitvavoErrorCode::AuthenticationRequired => 300,
+            BitvavoErrorCode::InvalidApiKeyLength => 301,
+            BitvavoErrorCode::InvalidTimestamp => 302,
+            BitvavoErrorCode::InvalidWindow => 303,
+            BitvavoErrorCode::OutsideAcceptanceWindow => 304,
+            BitvavoErrorCode::NoActiveApiKey => 305,
+            BitvavoErrorCode::ApiKeyNotConfirmed => 306,
+            BitvavoErrorCode::IpNotAllowed => 307,
+            BitvavoErrorCode::InvalidSignatureLength => 308,
+            BitvavoErrorCode::InvalidSignature => 309,
+            BitvavoErrorCode::TradingNotAllowed => 310,
+            BitvavoErrorCode::AccountInfoNotAllowed => 311,
+            BitvavoErrorCode::WithdrawalNotAllowed => 312,
+            BitvavoErrorCode::AccountLocked => 317,
+            BitvavoErrorCode::DepositsUnavailable => 401,
+            BitvavoErrorCode::WithdrawalBelowMinimum => 406,
+            BitvavoErrorCode::WithdrawalInsufficientBalance => 408,
+            BitvavoErrorCode::WithdrawalsUnavailable => 410,
+            BitvavoErrorCode::Unknown(code) => *code,
+        }
+    }
+}
+
+impl From<u32> for BitvavoErrorCode {
+    fn from(code: u32) -> Self {
+        match code {
+            101 => BitvavoErrorCode::UnknownError,
+            102 => BitvavoErrorCode::InvalidJson,
+            103 => BitvavoErrorCode::RateLimited,
+            104 => BitvavoErrorCode::OrderRateLimited,
+            105 => BitvavoErrorCode::Banned,
+            107 => BitvavoErrorCode::MatchingEngineOverloaded,
+            108 => BitvavoErrorCode::MatchingEngineTimeout,
+            109 => BitvavoErrorCode::MatchingEngineNoResponse,
+            110 => BitvavoErrorCode::InvalidEndpoint,
+            202 => BitvavoErrorCode::ParameterNotSupportedForOrderType,
+            203 => BitvavoErrorCode::MissingOrIncompatibleParameters,
+            204 => BitvavoErrorCode::InvalidParameterForEndpoint,
+            205 => BitvavoErrorCode::InvalidParameter,
+            206 => BitvavoErrorCode::AmountAndAmountQuote,
+            210 => BitvavoErrorCode::AmountTooHigh,
+            211 => BitvavoErrorCode::PriceTooHigh,
+            212 => BitvavoErrorCode::AmountTooLow,
+            213 => BitvavoErrorCode::PriceTooLow,
+            214 => BitvavoErrorCode::PriceTooDetailed,
+            216 => BitvavoErrorCode::InsufficientBalance,
+            217 => BitvavoErrorCode::MinimumQuoteOrderSizeNotMet,
+            218 => BitvavoErrorCode::MinimumBaseOrderSizeNotMet,
+            219 => BitvavoErrorCode::MarketInactive,
+            220 => BitvavoErrorCode::MarketNotAllowed,
+            231 => BitvavoErrorCode::TimeInForceNotAllowed,
+            232 => BitvavoErrorCode::NothingToUpdate,
+            233 => BitvavoErrorCode::OrderNotActive,
+            234 => BitvavoErrorCode::MarketOrderNotUpdatable,
+            235 => BitvavoErrorCode::TooManyOpenOrders,
+            236 => BitvavoErrorCode::AmountAndAmountRemaining,
+            240 => BitvavoErrorCode::OrderNotFound,
+            300 => BitvavoErrorCode::AuthenticationRequired,
+            301 => BitvavoErrorCode::InvalidApiKeyLength,
+            302 => BitvavoErrorCode::InvalidTimestamp,
+            303 => BitvavoErrorCode::InvalidWindow,
+            304 => BitvavoErrorCode::OutsideAcceptanceWindow,
+            305 => BitvavoErrorCode::NoActiveApiKey,
+            306 => BitvavoErrorCode::ApiKeyNotConfirmed,
+            307 => BitvavoErrorCode::IpNotAllowed,
+            308 => BitvavoErrorCode::InvalidSignatureLength,
+            309 => BitvavoErrorCode::InvalidSignature,
+            310 => BitvavoErrorCode::TradingNotAllowed,
+            311 => BitvavoErrorCode::AccountInfoNotAllowed,
+            312 => BitvavoErrorCode::WithdrawalNotAllowed,
+            317 => BitvavoErrorCode::AccountLocked,
+            401 => BitvavoErrorCode::DepositsUnavailable,
+            406 => BitvavoErrorCode::WithdrawalBelowMinimum,
+            408 => BitvavoErrorCode::WithdrawalInsufficientBalance,
+            410 => BitvavoErrorCode::WithdrawalsUnavailable,
+            code => BitvavoErrorCode::Unknown(code),
+        }
+    }
+}
+
+impl From<BitvavoErrorCode> for u32 {
+    fn from(error_code: BitvavoErrorCode) -> Self {
+        error_code.code()
+    }
+}
+
+impl Display for BitvavoErrorCode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BitvavoErrorCode::Unknown(code) => write!(f, "Unknown({})", code),
+            error_code => write!(f, "{:?}({})", error_code, error_code.code()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deserialize_error_code() {
+        let error_code: BitvavoErrorCode = serde_json::from_str("309").unwrap();
+        assert_eq!(error_code, BitvavoErrorCode::InvalidSignature);
+
+        let error_code: BitvavoErrorCode = serde_json::from_str("999").unwrap();
+        assert_eq!(error_code, BitvavoErrorCode::Unknown(999));
+    }
+
+    #[test]
+    fn code_round_trip() {
+        for code in 100..500 {
+            assert_eq!(BitvavoErrorCode::from(code).code(), code);
+        }
+    }
+}
@@ -1,7 +1,13 @@
-use crate::candle::Candle;
+use crate::candle::{Candle, CandleEvent};
+use crate::decimal::Decimal;
+use crate::error_code::BitvavoErrorCode;
 use crate::market::Market;
+use crate::order::{
+    OrderStatus, OrderType, SelfTradePrevention, TimeInForce, TriggerReference, TriggerType,
+};
 use crate::price_level::Book;
 use crate::rug_float_serde::FloatWrapper;
+use crate::side::Side;
 use crate::sig::create_signature;
 use crate::trade::Trade;
 use serde::{Deserialize, Serialize};
@@ -12,18 +18,54 @@ use std::time::{SystemTime, UNIX_EPOCH};
 pub enum BitvavoEvent {
     Authenticated,
     Subscribed,
+    Unsubscribed,
+    // an update from the book channel, levels with a zero quantity are to be removed
     Book(Book),
-    Candle(Candle),
+    // a getBook response
+    BookSnapshot(Book),
+    // a candle event, with every row it carried
+    Candle(CandleEvent),
     Trade(Trade),
     Markets(Vec<Market>),
     TickerBook(TickerBookResponse),
-    Ticker24h(Ticker24h),
+    // a ticker24h update or a getTicker24h response
+    Ticker24h(Vec<Ticker24h>),
     Ticker(Ticker),
+    Time(u64),
+    Assets(Vec<Asset>),
+    TickerPrices(Vec<TickerPrice>),
+    // a getTrades response
+    Trades(Vec<Trade>),
+    // a getCandles response, most recent first
+    Candles(Vec<Candle>),
+    AccountTrades(Vec<AccountTrade>),
+    Account(Account),
+    Deposits(Vec<Deposit>),
+    Withdrawals(Vec<Withdrawal>),
+    DepositAddress(DepositAddress),
     Balances(HashMap<String, Balance>),
+    PlacedOrder(Box<Order>),
+    UpdatedOrder(Box<Order>),
+    // a getOrder response
+    Order(Box<Order>),
+    // a getOrders or getOrdersOpen response
+    Orders(Vec<Order>),
+    CancelledOrder(String),
+    CancelledOrders(Vec<String>),
+    // an `order` event from the account channel
+    OrderUpdate(Box<Order>),
+    // a `fill` event from the account channel
+    Fill(Fill),
+    Error {
+        action: Option<String>,
+        error_code: BitvavoErrorCode,
+        error: String,
+        request_id: Option<u64>,
+    },
 }
 
 impl BitvavoEvent {
-    pub fn from_candle(candle: Candle) -> Self {
+    pub fn from_candle(candle: CandleEvent) -> Self {
         BitvavoEvent::Candle(candle)
     }
 
@@ -35,7 +77,7 @@ impl BitvavoEvent {
         BitvavoEvent::Trade(trade)
     }
 
-    pub fn from_ticker24h(ticker24h: Ticker24h) -> Self {
+    pub fn from_ticker24h(ticker24h: Vec<Ticker24h>) -> Self {
         BitvavoEvent::Ticker24h(ticker24h)
     }
 
@@ -48,34 +90,57 @@ impl BitvavoEvent {
 #[derive(Serialize, Deserialize)]
 pub struct TimeResponse {
     action: String,
-    response: Time,
+    pub response: Time,
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct Time {
-    time: u32,
+    // milliseconds since the epoch
+    pub time: u64,
 }
 
-// AssetsResponse and Assets
+// AssetsResponse and Asset
 #[derive(Serialize, Deserialize)]
 pub struct AssetsResponse {
     action: String,
-    response: Vec<Assets>,
+    pub response: OneOrMany<Asset>,
 }
 
-#[derive(Serialize, Deserialize)]
-pub struct Assets {
-    symbol: String,
-    name: String,
-    decimals: u32,
-    deposit_fee: String,
-    deposit_confirmations: u32,
-    deposit_status: String,
-    withdrawal_fee: String,
-    withdrawal_min_amount: String,
-    withdrawal_status: String,
-    networks: Vec<String>,
-    message: String,
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Asset {
+    pub symbol: String,
+    pub name: String,
+    pub decimals: u32,
+    pub deposit_fee: FloatWrapper,
+    pub deposit_confirmations: u32,
+    // OK, MAINTENANCE or DELISTED
+    pub deposit_status: String,
+    pub withdrawal_fee: FloatWrapper,
+    pub withdrawal_min_amount: FloatWrapper,
+    pub withdrawal_status: String,
+    #[serde(default)]
+    pub networks: Vec<String>,
+    #[serde(default)]
+    pub message: String,
+}
+
+// most actions answer with a single object when asked about one market or asset,
+// and with a list otherwise
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(untagged)]
+pub enum OneOrMany<T> {
+    One(T),
+    Many(Vec<T>),
+}
+
+impl<T> OneOrMany<T> {
+    pub fn into_vec(self) -> Vec<T> {
+        match self {
+            OneOrMany::One(one) => vec![one],
+            OneOrMany::Many(many) => many,
+        }
+    }
 }
 
 // BookResponse and Book
@@ -84,59 +149,66 @@ pub struct BookResponse {
     pub response: Book,
 }
 
-// PublicTradesResponse and PublicTrades
+// PublicTradesResponse and Trade
 #[derive(Serialize, Deserialize)]
-pub struct PublicTradesResponswe {
+pub struct PublicTradesResponse {
     action: String,
-    response: Vec<Trade>,
+    pub response: Vec<Trade>,
 }
 
-// Ticker24hResponse and Ticker24h
+// Ticker24hResponse, Ticker24hEvent and Ticker24h
 #[derive(Serialize, Deserialize)]
 pub struct Ticker24hResponse {
     action: String,
-    response: Vec<Ticker24h>,
+    pub response: OneOrMany<Ticker24h>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Ticker24hEvent {
+    pub data: Vec<Ticker24h>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Ticker {
-    market: String,
+    pub market: String,
     #[serde(rename = "bestBid")]
-    pub best_bid: Option<FloatWrapper>,
+    pub best_bid: Option<Decimal>,
     #[serde(rename = "bestBidSize")]
-    pub best_bid_size: Option<FloatWrapper>,
+    pub best_bid_size: Option<Decimal>,
     #[serde(rename = "bestAsk")]
-    pub best_ask: Option<FloatWrapper>,
+    pub best_ask: Option<Decimal>,
     #[serde(rename = "bestAskSize")]
-    pub best_ask_size: Option<FloatWrapper>,
+    pub best_ask_size: Option<Decimal>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+// the prices are null for markets that didn't trade in the last 24 hours
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
 pub struct Ticker24h {
-    market: String,
-    open: String,
-    high: String,
-    low: String,
-    last: String,
-    volume: String,
-    volume_quote: String,
-    bid: String,
-    ask: String,
-    timestamp: u64,
-    bid_size: String,
-    ask_size: String,
+    pub market: String,
+    pub open: Option<FloatWrapper>,
+    pub high: Option<FloatWrapper>,
+    pub low: Option<FloatWrapper>,
+    pub last: Option<FloatWrapper>,
+    pub volume: Option<FloatWrapper>,
+    pub volume_quote: Option<FloatWrapper>,
+    pub bid: Option<FloatWrapper>,
+    pub bid_size: Option<FloatWrapper>,
+    pub ask: Option<FloatWrapper>,
+    pub ask_size: Option<FloatWrapper>,
+    pub timestamp: u64,
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct TickerPriceResponse {
     action: String,
-    response: Vec<TickerPrice>,
+    pub response: OneOrMany<TickerPrice>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct TickerPrice {
-    market: String,
-    price: String,
+    pub market: String,
+    pub price: Option<FloatWrapper>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -145,10 +217,14 @@ pub struct TickerBookResponse {
     response: TickerBook,
 }
 
+// what the exchange sends back instead of a response when an action fails
 #[derive(Serialize, Deserialize, Debug)]
-pub struct SubscriptionResponse {
-    pub error: Option<String>,
-    pub error_code: Option<String>,
+#[serde(rename_all = "camelCase")]
+pub struct ErrorResponse {
+    pub action: Option<String>,
+    pub error_code: BitvavoErrorCode,
+    pub error: String,
+    pub request_id: Option<u64>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -161,89 +237,116 @@ pub struct TickerBook {
     ask_size: String,
 }
 
-// PlaceOrderResponse, GetOrderResponse, UpdateOrderResponse, and CancelOrderResponse
+// PlaceOrderResponse, GetOrderResponse, GetOrdersResponse, UpdateOrderResponse, CancelOrderResponse
+// and CancelOrdersResponse
 #[derive(Serialize, Deserialize, Debug)]
 pub struct PlaceOrderResponse {
     action: String,
-    response: Order,
+    pub response: Order,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct GetOrderResponse {
     action: String,
-    response: Order,
+    pub response: Order,
+}
+
+// getOrders and getOrdersOpen
+#[derive(Serialize, Deserialize, Debug)]
+pub struct GetOrdersResponse {
+    action: String,
+    pub response: Vec<Order>,
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct UpdateOrderResponse {
     action: String,
-    response: Order,
+    pub response: Order,
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct CancelOrderResponse {
     action: String,
-    response: CancelOrder,
+    pub response: CancelOrder,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct CancelOrdersResponse {
+    action: String,
+    pub response: Vec<CancelOrder>,
 }
 
 // Order and CancelOrder
-#[derive(Serialize, Deserialize, Debug)]
+// the exchange omits the fields that don't apply to the order type or status
+#[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct Order {
-    order_id: String,
-    market: String,
-    created: u32,
-    updated: u32,
-    status: String,
-    side: String,
-    order_type: String,
-    amount: String,
-    amount_remaining: String,
-    price: String,
-    amount_quote: String,
-    amount_quote_remaining: String,
-    on_hold: String,
-    on_hold_currency: String,
-    filled_amount: String,
-    filled_amount_quote: String,
-    fee_paid: String,
-    fee_currency: String,
-    fills: Vec<Fill>,
-    self_trade_prevention: String,
-    visible: bool,
-    disable_market_protection: bool,
-    time_in_force: String,
-    post_only: bool,
-    trigger_amount: String,
-    trigger_price: String,
-    trigger_type: String,
-    trigger_reference: String,
+    pub order_id: String,
+    pub market: String,
+    pub created: u64,
+    pub updated: u64,
+    pub status: OrderStatus,
+    pub side: Side,
+    pub order_type: OrderType,
+    pub amount: Option<FloatWrapper>,
+    pub amount_remaining: Option<FloatWrapper>,
+    pub price: Option<FloatWrapper>,
+    pub amount_quote: Option<FloatWrapper>,
+    pub amount_quote_remaining: Option<FloatWrapper>,
+    pub on_hold: Option<FloatWrapper>,
+    pub on_hold_currency: Option<String>,
+    pub filled_amount: Option<FloatWrapper>,
+    pub filled_amount_quote: Option<FloatWrapper>,
+    pub fee_paid: Option<FloatWrapper>,
+    pub fee_currency: Option<String>,
+    #[serde(default)]
+    pub fills: Vec<Fill>,
+    pub self_trade_prevention: Option<SelfTradePrevention>,
+    #[serde(default)]
+    pub visible: bool,
+    #[serde(default)]
+    pub disable_market_protection: bool,
+    pub time_in_force: Option<TimeInForce>,
+    #[serde(default)]
+    pub post_only: bool,
+    pub trigger_amount: Option<FloatWrapper>,
+    pub trigger_price: Option<FloatWrapper>,
+    pub trigger_type: Option<TriggerType>,
+    pub trigger_reference: Option<TriggerReference>,
 }
 
 #[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct CancelOrder {
-    order_id: String,
+    pub order_id: String,
 }
 
-// Fill
-#[derive(Serialize, Deserialize, Debug)]
-struct Fill {
-    id: String,
-    timestamp: u64,
-    amount: String,
-    price: String,
-    taker: bool,
-    fee: String,
-    fee_currency: String,
-    settled: bool,
+// Fill, either part of an Order or a `fill` event from the account channel; only
+// the latter carries the order, market and side, and calls its id `fillId`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Fill {
+    #[serde(alias = "fillId")]
+    pub id: String,
+    pub order_id: Option<String>,
+    pub market: Option<String>,
+    pub side: Option<Side>,
+    pub timestamp: u64,
+    pub amount: FloatWrapper,
+    pub price: FloatWrapper,
+    pub taker: bool,
+    pub fee: Option<FloatWrapper>,
+    pub fee_currency: Option<String>,
+    #[serde(default)]
+    pub settled: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct Balance {
     pub symbol: String,
-    pub available: FloatWrapper,
-    pub in_order: FloatWrapper,
+    pub available: Decimal,
+    pub in_order: Decimal,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -251,6 +354,110 @@ pub struct GetBalancesResponse {
     pub response: Vec<Balance>,
 }
 
+// privateGetTrades: the account's own trades, each a fill of one of its orders
+#[derive(Serialize, Deserialize)]
+pub struct AccountTradesResponse {
+    action: String,
+    pub response: Vec<AccountTrade>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct AccountTrade {
+    pub id: String,
+    pub order_id: String,
+    pub client_order_id: Option<String>,
+    pub timestamp: u64,
+    pub market: String,
+    pub side: Side,
+    pub amount: FloatWrapper,
+    pub price: FloatWrapper,
+    pub taker: bool,
+    pub fee: FloatWrapper,
+    pub fee_currency: String,
+    pub settled: bool,
+}
+
+// privateGetAccount
+#[derive(Serialize, Deserialize)]
+pub struct AccountResponse {
+    action: String,
+    pub response: Account,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Account {
+    pub fees: FeeTier,
+    #[serde(default)]
+    pub capabilities: Vec<String>,
+}
+
+// the fee rates of the account, depending on its 30 days volume
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FeeTier {
+    pub tier: Option<u32>,
+    pub volume: FloatWrapper,
+    pub maker: FloatWrapper,
+    pub taker: FloatWrapper,
+}
+
+// privateGetDepositHistory and privateGetWithdrawalHistory
+#[derive(Serialize, Deserialize)]
+pub struct DepositHistoryResponse {
+    action: String,
+    pub response: Vec<Deposit>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Deposit {
+    pub timestamp: u64,
+    pub symbol: String,
+    pub amount: FloatWrapper,
+    pub fee: FloatWrapper,
+    pub status: String,
+    pub address: Option<String>,
+    pub payment_id: Option<String>,
+    pub tx_id: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct WithdrawalHistoryResponse {
+    action: String,
+    pub response: Vec<Withdrawal>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Withdrawal {
+    pub timestamp: u64,
+    pub symbol: String,
+    pub amount: FloatWrapper,
+    pub fee: FloatWrapper,
+    pub status: String,
+    pub address: Option<String>,
+    pub payment_id: Option<String>,
+    pub tx_id: Option<String>,
+}
+
+// privateDepositAssets: an address (and payment id, for some assets) to deposit
+// crypto to, or the bank details to deposit fiat to
+#[derive(Serialize, Deserialize)]
+pub struct DepositAssetsResponse {
+    action: String,
+    pub response: DepositAddress,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct DepositAddress {
+    pub address: Option<String>,
+    pub payment_id: Option<String>,
+    pub iban: Option<String>,
+    pub bic: Option<String>,
+    pub description: Option<String>,
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct AuthRequest {
     action: String,
@@ -1,13 +1,21 @@
-#![feature(let_chains)]
-
 pub mod bitvavo;
+pub mod book_manager;
 pub mod candle;
+pub mod candle_aggregator;
+pub mod candle_series;
+pub mod connection;
+pub mod decimal;
 pub mod decode;
+pub mod error_code;
 pub mod event;
 pub mod local_book;
 pub mod market;
+pub mod market_registry;
+pub mod order;
 pub mod price_level;
 pub mod rug_float_serde;
 pub mod side;
 pub mod sig;
+pub mod subscription;
 pub mod trade;
+pub mod trade_tape;
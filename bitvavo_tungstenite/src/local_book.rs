@@ -1,64 +1,426 @@
+use crate::decimal::Decimal;
 use crate::event::Ticker;
 use crate::price_level::{Book, PriceLevel};
-use crate::rug_float_serde::FloatWrapper;
+use crate::side::Side;
+use std::collections::BTreeMap;
 
+// updates kept while waiting for a snapshot
+pub const MAX_BUFFERED_UPDATES: usize = 1024;
+
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum BookSync {
+    InSync,
+    // a snapshot has to be requested (see Bitvavo::request_book), updates are
+    // buffered until it arrives
+    NeedsSnapshot,
+    // a snapshot is on its way, updates are buffered
+    Buffering,
+}
+
+// both sides are keyed by price in ascending order: the best bid is the last
+// entry, the best ask the first one
 #[derive(Debug, Default)]
 pub struct LocalBook {
     price_level_default: PriceLevel,
-    bids: Vec<PriceLevel>,
-    asks: Vec<PriceLevel>,
+    bids: BTreeMap<Decimal, PriceLevel>,
+    asks: BTreeMap<Decimal, PriceLevel>,
+    nonce: Option<u64>,
+    resyncing: bool,
+    buffered: Vec<Book>,
 }
 
 impl LocalBook {
     pub fn top_bid_or_default(&self) -> &PriceLevel {
-        self.bids.first().unwrap_or(&self.price_level_default)
+        self.top_bid().unwrap_or(&self.price_level_default)
     }
 
     pub fn top_ask_or_default(&self) -> &PriceLevel {
-        self.asks.first().unwrap_or(&self.price_level_default)
+        self.top_ask().unwrap_or(&self.price_level_default)
+    }
+
+    pub fn top_bid(&self) -> Option<&PriceLevel> {
+        self.bids.values().next_back()
+    }
+
+    pub fn top_ask(&self) -> Option<&PriceLevel> {
+        self.asks.values().next()
+    }
+
+    // the levels of one side of the book, best first: bids for Buy, asks for Sell
+    pub fn levels(&self, side: Side) -> Box<dyn Iterator<Item = &PriceLevel> + '_> {
+        match side {
+            Side::Buy => Box::new(self.bids.values().rev()),
+            Side::Sell => Box::new(self.asks.values()),
+        }
+    }
+
+    pub fn depth(&self, side: Side, levels: usize) -> Vec<&PriceLevel> {
+        self.levels(side).take(levels).collect()
     }
 
-    pub fn real_spread_or_default(&self) -> FloatWrapper {
-        let best_bid = self.bids.first();
-        let best_ask = self.asks.first();
-        if let Some(bid) = best_bid
-            && let Some(ask) = best_ask
+    pub fn real_spread_or_default(&self) -> Decimal {
+        if let Some(bid) = self.top_bid()
+            && let Some(ask) = self.top_ask()
         {
-            let market = (bid.price.float.clone() + ask.price.float.clone()) / 2;
-            let spread = ask.price.float.clone() - bid.price.float.clone();
-            return FloatWrapper::from(spread / market);
+            let market = (bid.price + ask.price) / Decimal::from(2);
+            let spread = ask.price - bid.price;
+            return spread / market;
         }
-        FloatWrapper::default()
+        Decimal::default()
     }
 
-    // when ingesting a ticker only the top of the book is available
-    pub fn ingest_ticker(&mut self, ticker: Ticker) {
-        if let Some(best_bid) = ticker.best_bid {
-            self.bids = Vec::new();
-            self.bids.push(PriceLevel {
-                price: best_bid,
-                quantity: ticker.best_bid_size.unwrap(),
-            })
+    pub fn mid_price(&self) -> Option<Decimal> {
+        let bid = self.top_bid()?;
+        let ask = self.top_ask()?;
+        Some((bid.price + ask.price) / Decimal::from(2))
+    }
+
+    // the mid price weighted by the size on the opposite side of the top of book
+    pub fn microprice(&self) -> Option<Decimal> {
+        let bid = self.top_bid()?;
+        let ask = self.top_ask()?;
+        let total = bid.quantity + ask.quantity;
+        if total.is_zero() {
+            return None;
         }
-        if let Some(best_ask) = ticker.best_ask {
-            self.asks = Vec::new();
-            self.asks.push(PriceLevel {
-                price: best_ask,
-                quantity: ticker.best_ask_size.unwrap(),
+        Some((bid.price * ask.quantity + ask.price * bid.quantity) / total)
+    }
+
+    // total quantity on `side` at prices at least as good as `price`
+    pub fn cumulative_quantity(&self, side: Side, price: Decimal) -> Decimal {
+        self.levels(side)
+            .take_while(|pl| match side {
+                Side::Buy => pl.price >= price,
+                Side::Sell => pl.price <= price,
             })
+            .map(|pl| pl.quantity)
+            .sum()
+    }
+
+    // the average price a market order of `size` on `side` would get, walking the
+    // opposite side of the book; None if the book isn't deep enough
+    pub fn vwap_to_fill(&self, side: Side, size: Decimal) -> Option<Decimal> {
+        if size <= Decimal::ZERO {
+            return None;
+        }
+        let mut remaining = size;
+        let mut notional = Decimal::ZERO;
+        for pl in self.levels(opposite(side)) {
+            let filled = pl.quantity.min(remaining);
+            notional += filled * pl.price;
+            remaining -= filled;
+            if remaining.is_zero() {
+                return Some(notional / size);
+            }
+        }
+        None
+    }
+
+    // how much worse than the best price a market order of `size` on `side` would
+    // fill on average, as a fraction of the best price
+    pub fn expected_slippage(&self, side: Side, size: Decimal) -> Option<Decimal> {
+        let best = self.levels(opposite(side)).next()?.price;
+        let vwap = self.vwap_to_fill(side, size)?;
+        let slippage = match side {
+            Side::Buy => vwap - best,
+            Side::Sell => best - vwap,
+        };
+        Some(slippage / best)
+    }
+
+    // the nonce of the last applied snapshot or update
+    pub fn nonce(&self) -> Option<u64> {
+        self.nonce
+    }
+
+    pub fn is_in_sync(&self) -> bool {
+        self.nonce.is_some() && !self.resyncing
+    }
+
+    // a ticker only has the top of the book, it stands in for the book channel
+    // until that is in sync and is ignored from then on; a side without a price
+    // and a size is left as it is
+    pub fn ingest_ticker(&mut self, ticker: Ticker) {
+        if self.is_in_sync() {
+            return;
+        }
+        if let (Some(price), Some(quantity)) = (ticker.best_bid, ticker.best_bid_size)
+            && !quantity.is_zero()
+        {
+            self.bids.clear();
+            apply_level(&mut self.bids, PriceLevel { price, quantity });
+        }
+        if let (Some(price), Some(quantity)) = (ticker.best_ask, ticker.best_ask_size)
+            && !quantity.is_zero()
+        {
+            self.asks.clear();
+            apply_level(&mut self.asks, PriceLevel { price, quantity });
+        }
+    }
+
+    // applies an update from the book channel; an update that doesn't follow the
+    // last nonce puts the book out of sync until a snapshot is ingested
+    pub fn ingest_book(&mut self, book: Book) -> BookSync {
+        if self.resyncing {
+            self.buffered.push(book);
+            // the snapshot got lost or was too old, a new one covers the oldest
+            // half of the updates
+            if self.buffered.len() >= MAX_BUFFERED_UPDATES {
+                self.buffered.drain(..MAX_BUFFERED_UPDATES / 2);
+                return BookSync::NeedsSnapshot;
+            }
+            return BookSync::Buffering;
+        }
+        match self.nonce {
+            Some(nonce) if book.nonce == nonce + 1 => {
+                self.apply(book);
+                BookSync::InSync
+            }
+            // already part of what we have
+            Some(nonce) if book.nonce <= nonce => BookSync::InSync,
+            _ => {
+                log::warn!(
+                    "book nonce gap: expected {:?}, got {}",
+                    self.nonce.map(|n| n + 1),
+                    book.nonce
+                );
+                self.begin_resync();
+                self.buffered.push(book);
+                BookSync::NeedsSnapshot
+            }
+        }
+    }
+
+    // starts buffering updates until the next snapshot, e.g. right before
+    // requesting the first one
+    pub fn begin_resync(&mut self) {
+        self.resyncing = true;
+    }
+
+    // replaces the book with a getBook response and replays the updates buffered
+    // in the meantime
+    pub fn ingest_snapshot(&mut self, book: Book) -> BookSync {
+        self.bids.clear();
+        self.asks.clear();
+        for level in book.bids {
+            apply_level(&mut self.bids, level);
+        }
+        for level in book.asks {
+            apply_level(&mut self.asks, level);
+        }
+        self.nonce = Some(book.nonce);
+        self.resyncing = false;
+
+        let mut buffered = std::mem::take(&mut self.buffered);
+        buffered.sort_by_key(|update| update.nonce);
+        let mut sync = BookSync::InSync;
+        for update in buffered {
+            if sync == BookSync::InSync {
+                sync = self.ingest_book(update);
+            } else {
+                self.buffered.push(update);
+            }
+        }
+        sync
+    }
+
+    fn apply(&mut self, book: Book) {
+        for level in book.bids {
+            apply_level(&mut self.bids, level);
+        }
+        for level in book.asks {
+            apply_level(&mut self.asks, level);
+        }
+        self.nonce = Some(book.nonce);
+    }
+}
+
+fn apply_level(levels: &mut BTreeMap<Decimal, PriceLevel>, level: PriceLevel) {
+    if level.quantity.is_zero() {
+        levels.remove(&level.price);
+    } else {
+        levels.insert(level.price, level);
+    }
+}
+
+fn opposite(side: Side) -> Side {
+    match side {
+        Side::Buy => Side::Sell,
+        Side::Sell => Side::Buy,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn book(nonce: u64, bids: &[(&str, &str)], asks: &[(&str, &str)]) -> Book {
+        let levels = |levels: &[(&str, &str)]| {
+            levels
+                .iter()
+                .map(|(price, quantity)| {
+                    serde_json::from_value(serde_json::json!([price, quantity])).unwrap()
+                })
+                .collect()
+        };
+        Book {
+            market: "BTC-EUR".to_string(),
+            nonce,
+            bids: levels(bids),
+            asks: levels(asks),
         }
     }
 
-    pub fn ingest_book(&mut self, book: Book) {
-        let bids = book
-            .bids
-            .into_iter()
-            .skip_while(|pl| pl.quantity.float == 0);
-        let asks = book
-            .asks
-            .into_iter()
-            .skip_while(|pl| pl.quantity.float == 0);
-        self.bids = bids.collect();
-        self.asks = asks.collect();
+    fn prices(local_book: &LocalBook, side: Side) -> Vec<String> {
+        local_book
+            .levels(side)
+            .map(|pl| pl.price.to_string())
+            .collect()
+    }
+
+    fn d(s: &str) -> Decimal {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn apply_updates() {
+        let mut local_book = LocalBook::default();
+        local_book.ingest_snapshot(book(
+            10,
+            &[("100", "1"), ("99", "1")],
+            &[("101", "1"), ("102", "1")],
+        ));
+
+        let sync = local_book.ingest_book(book(
+            11,
+            &[("100.5", "2"), ("99", "0")],
+            &[("101", "3"), ("103", "1")],
+        ));
+
+        assert_eq!(sync, BookSync::InSync);
+        assert_eq!(prices(&local_book, Side::Buy), vec!["100.5", "100"]);
+        assert_eq!(prices(&local_book, Side::Sell), vec!["101", "102", "103"]);
+        assert_eq!(local_book.top_ask_or_default().quantity.to_string(), "3");
+        assert_eq!(local_book.nonce(), Some(11));
+    }
+
+    #[test]
+    fn gap_buffers_until_snapshot() {
+        let mut local_book = LocalBook::default();
+        local_book.ingest_snapshot(book(10, &[("100", "1")], &[("101", "1")]));
+
+        assert_eq!(
+            local_book.ingest_book(book(12, &[("99", "1")], &[])),
+            BookSync::NeedsSnapshot
+        );
+        assert!(!local_book.is_in_sync());
+        assert_eq!(
+            local_book.ingest_book(book(13, &[("98", "1")], &[])),
+            BookSync::Buffering
+        );
+
+        // the snapshot already contains update 12
+        let sync =
+            local_book.ingest_snapshot(book(12, &[("100", "1"), ("99", "1")], &[("101", "1")]));
+
+        assert_eq!(sync, BookSync::InSync);
+        assert!(local_book.is_in_sync());
+        assert_eq!(prices(&local_book, Side::Buy), vec!["100", "99", "98"]);
+        assert_eq!(local_book.nonce(), Some(13));
+    }
+
+    #[test]
+    fn first_update_needs_snapshot() {
+        let mut local_book = LocalBook::default();
+        assert_eq!(
+            local_book.ingest_book(book(5, &[("100", "1")], &[])),
+            BookSync::NeedsSnapshot
+        );
+
+        // a snapshot older than the buffered update still leaves a gap
+        let sync = local_book.ingest_snapshot(book(3, &[], &[]));
+        assert_eq!(sync, BookSync::NeedsSnapshot);
+        assert!(!local_book.is_in_sync());
+
+        // without a snapshot the buffer stays bounded and asks again
+        let syncs: Vec<_> = (6..6 + MAX_BUFFERED_UPDATES as u64)
+            .map(|nonce| local_book.ingest_book(book(nonce, &[], &[])))
+            .collect();
+        assert_eq!(
+            syncs
+                .iter()
+                .filter(|s| **s == BookSync::NeedsSnapshot)
+                .count(),
+            1
+        );
+        let sync = local_book.ingest_snapshot(book(600, &[("100", "1")], &[]));
+        assert_eq!(sync, BookSync::InSync);
+        assert_eq!(local_book.nonce(), Some(5 + MAX_BUFFERED_UPDATES as u64));
+        assert!(local_book.buffered.is_empty());
+    }
+
+    #[test]
+    fn tickers_only_until_in_sync() {
+        let ticker = |bid: Option<&str>, bid_size: Option<&str>| Ticker {
+            market: "BTC-EUR".to_string(),
+            best_bid: bid.map(d),
+            best_bid_size: bid_size.map(d),
+            best_ask: Some(d("101")),
+            best_ask_size: Some(d("2")),
+        };
+        let mut local_book = LocalBook::default();
+        local_book.ingest_ticker(ticker(Some("100"), Some("1")));
+        // no size, the bid stays
+        local_book.ingest_ticker(ticker(Some("99"), None));
+        assert_eq!(local_book.top_bid().unwrap().price, d("100"));
+        assert_eq!(local_book.top_ask().unwrap().quantity, d("2"));
+
+        local_book.ingest_snapshot(book(
+            1,
+            &[("100", "1"), ("99", "1")],
+            &[("101", "1"), ("102", "1")],
+        ));
+        local_book.ingest_ticker(ticker(Some("100.5"), Some("3")));
+        assert_eq!(prices(&local_book, Side::Buy), vec!["100", "99"]);
+        assert_eq!(prices(&local_book, Side::Sell), vec!["101", "102"]);
+    }
+
+    #[test]
+    fn depth_queries() {
+        let mut local_book = LocalBook::default();
+        local_book.ingest_snapshot(book(
+            1,
+            &[("99", "1"), ("98", "2"), ("97", "4")],
+            &[("101", "3"), ("102", "1"), ("104", "2")],
+        ));
+
+        let depth = local_book.depth(Side::Sell, 2);
+        assert_eq!(depth.len(), 2);
+        assert_eq!(depth[1].price, d("102"));
+
+        assert_eq!(local_book.cumulative_quantity(Side::Buy, d("98")), d("3"));
+        assert_eq!(local_book.cumulative_quantity(Side::Sell, d("103")), d("4"));
+        assert_eq!(local_book.mid_price(), Some(d("100")));
+        // (99 * 3 + 101 * 1) / 4
+        assert_eq!(local_book.microprice(), Some(d("99.5")));
+
+        // 3 @ 101 + 1 @ 102
+        assert_eq!(
+            local_book.vwap_to_fill(Side::Buy, d("4")),
+            Some(d("101.25"))
+        );
+        assert_eq!(local_book.vwap_to_fill(Side::Buy, d("7")), None);
+        // 1 @ 99 + 1 @ 98
+        assert_eq!(local_book.vwap_to_fill(Side::Sell, d("2")), Some(d("98.5")));
+
+        assert_eq!(
+            local_book.expected_slippage(Side::Buy, d("3")),
+            Some(Decimal::ZERO)
+        );
+        // 0.5 / 99
+        assert_eq!(
+            local_book.expected_slippage(Side::Sell, d("2")),
+            Some(d("0.005050505050505051"))
+        );
     }
 }
@@ -1,17 +1,275 @@
+use crate::decimal::{Decimal, Rounding};
+use crate::order::{OrderRequest, OrderType};
+use crate::rug_float_serde::FloatWrapper;
+use crate::side::Side;
 use serde::{Deserialize, Serialize};
 use std::fmt::{Display, Formatter};
+use std::str::FromStr;
 
-#[derive(Serialize, Deserialize, Debug)]
+// the significant digits of a price when the market doesn't say
+pub const DEFAULT_PRICE_PRECISION: u32 = 5;
+
+#[derive(Debug, Eq, PartialEq, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MarketStatus {
+    Trading,
+    Halted,
+    // only limit orders are collected, and matched once the auction ends
+    Auction,
+    // a status this crate doesn't know yet, rather than failing the whole list
+    #[serde(other)]
+    Unknown,
+}
+
+impl Display for MarketStatus {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                MarketStatus::Trading => "trading",
+                MarketStatus::Halted => "halted",
+                MarketStatus::Auction => "auction",
+                MarketStatus::Unknown => "unknown",
+            }
+        )
+    }
+}
+
+// a market symbol split into its assets, BTC-EUR is BTC priced in EUR
+#[derive(Debug, Eq, PartialEq, Hash, Clone, PartialOrd, Ord)]
+pub struct Pair {
+    pub base: String,
+    pub quote: String,
+}
+
+impl FromStr for Pair {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once('-') {
+            Some((base, quote))
+                if !base.is_empty() && !quote.is_empty() && !quote.contains('-') =>
+            {
+                Ok(Pair {
+                    base: base.to_string(),
+                    quote: quote.to_string(),
+                })
+            }
+            _ => Err(format!("{} is not a BASE-QUOTE market symbol", s)),
+        }
+    }
+}
+
+impl Display for Pair {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}-{}", self.base, self.quote)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct Market {
-    status: String,
+    status: MarketStatus,
     base: String,
     quote: String,
     market: String,
     price_precision: Option<u32>,
-    min_order_in_quote_asset: Option<String>,
-    min_order_in_base_asset: Option<String>,
-    order_types: Option<Vec<String>>,
+    min_order_in_quote_asset: Option<Decimal>,
+    min_order_in_base_asset: Option<Decimal>,
+    order_types: Option<Vec<OrderType>>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum OrderValidationError {
+    UnknownMarket(String),
+    UnknownAsset(String),
+    MarketHalted(String),
+    WrongMarket {
+        expected: String,
+        got: String,
+    },
+    OrderTypeNotAllowed(OrderType),
+    // the amount or price isn't plain decimal notation
+    NotDecimal(String),
+    AmountRoundsToZero {
+        amount: Decimal,
+        decimals: u32,
+    },
+    BelowMinimumBase {
+        amount: Decimal,
+        minimum: Decimal,
+    },
+    BelowMinimumQuote {
+        amount_quote: Decimal,
+        minimum: Decimal,
+    },
+}
+
+impl Display for OrderValidationError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OrderValidationError::UnknownMarket(market) => write!(f, "unknown market {}", market),
+            OrderValidationError::UnknownAsset(asset) => write!(f, "unknown asset {}", asset),
+            OrderValidationError::MarketHalted(market) => write!(f, "{} is halted", market),
+            OrderValidationError::WrongMarket { expected, got } => {
+                write!(f, "order for {} validated against {}", got, expected)
+            }
+            OrderValidationError::OrderTypeNotAllowed(order_type) => {
+                write!(f, "{} orders are not allowed in this market", order_type)
+            }
+            OrderValidationError::NotDecimal(value) => write!(f, "{} is not a decimal", value),
+            OrderValidationError::AmountRoundsToZero { amount, decimals } => {
+                write!(
+                    f,
+                    "amount {} rounds to zero with {} decimals",
+                    amount, decimals
+                )
+            }
+            OrderValidationError::BelowMinimumBase { amount, minimum } => {
+                write!(f, "amount {} is below the minimum of {}", amount, minimum)
+            }
+            OrderValidationError::BelowMinimumQuote {
+                amount_quote,
+                minimum,
+            } => write!(
+                f,
+                "order value {} is below the minimum of {}",
+                amount_quote, minimum
+            ),
+        }
+    }
+}
+
+impl Market {
+    pub fn market(&self) -> &str {
+        &self.market
+    }
+
+    pub fn base(&self) -> &str {
+        &self.base
+    }
+
+    pub fn quote(&self) -> &str {
+        &self.quote
+    }
+
+    pub fn status(&self) -> MarketStatus {
+        self.status
+    }
+
+    pub fn pair(&self) -> Pair {
+        Pair {
+            base: self.base.clone(),
+            quote: self.quote.clone(),
+        }
+    }
+
+    // the significant digits allowed in a price
+    pub fn price_precision(&self) -> u32 {
+        self.price_precision.unwrap_or(DEFAULT_PRICE_PRECISION)
+    }
+
+    pub fn min_order_in_base_asset(&self) -> Option<Decimal> {
+        self.min_order_in_base_asset
+    }
+
+    pub fn min_order_in_quote_asset(&self) -> Option<Decimal> {
+        self.min_order_in_quote_asset
+    }
+
+    // None when the exchange doesn't restrict them
+    pub fn order_types(&self) -> Option<&[OrderType]> {
+        self.order_types.as_deref()
+    }
+
+    pub fn allows(&self, order_type: OrderType) -> bool {
+        self.order_types
+            .as_ref()
+            .is_none_or(|order_types| order_types.contains(&order_type))
+    }
+
+    // rounds a price to the significant digits of the market, in favour of whoever
+    // places the order: down when buying, up when selling
+    pub fn round_price(&self, price: Decimal, side: Side) -> Decimal {
+        let rounding = match side {
+            Side::Buy => Rounding::Down,
+            Side::Sell => Rounding::Up,
+        };
+        price.round_significant(self.price_precision(), rounding)
+    }
+
+    // checks an order against the rules of the market before it is placed, and
+    // returns it with its prices rounded to the market's precision and its amount
+    // truncated to `amount_decimals`, the decimals of the base asset
+    pub fn validate_order(
+        &self,
+        mut order: OrderRequest,
+        amount_decimals: u32,
+    ) -> Result<OrderRequest, OrderValidationError> {
+        if order.market != self.market {
+            return Err(OrderValidationError::WrongMarket {
+                expected: self.market.clone(),
+                got: order.market,
+            });
+        }
+        if !self.allows(order.order_type) {
+            return Err(OrderValidationError::OrderTypeNotAllowed(order.order_type));
+        }
+
+        let price = match &order.price {
+            Some(price) => Some(self.round_price(decimal(price)?, order.side)),
+            None => None,
+        };
+        order.price = price.map(FloatWrapper::from);
+        if let Some(trigger_amount) = &order.trigger_amount {
+            let trigger_amount = self.round_price(decimal(trigger_amount)?, order.side);
+            order.trigger_amount = Some(FloatWrapper::from(trigger_amount));
+        }
+
+        if let Some(amount) = &order.amount {
+            let proposed = decimal(amount)?;
+            let amount = proposed.round_dp_with(amount_decimals, Rounding::Down);
+            if amount.is_zero() {
+                return Err(OrderValidationError::AmountRoundsToZero {
+                    amount: proposed,
+                    decimals: amount_decimals,
+                });
+            }
+            if let Some(minimum) = self.min_order_in_base_asset
+                && amount < minimum
+            {
+                return Err(OrderValidationError::BelowMinimumBase { amount, minimum });
+            }
+            // the value of a market order with an amount is only known once filled
+            if let (Some(price), Some(minimum)) = (price, self.min_order_in_quote_asset)
+                && amount * price < minimum
+            {
+                return Err(OrderValidationError::BelowMinimumQuote {
+                    amount_quote: amount * price,
+                    minimum,
+                });
+            }
+            order.amount = Some(FloatWrapper::from(amount));
+        }
+
+        if let Some(amount_quote) = &order.amount_quote {
+            let amount_quote = decimal(amount_quote)?;
+            if let Some(minimum) = self.min_order_in_quote_asset
+                && amount_quote < minimum
+            {
+                return Err(OrderValidationError::BelowMinimumQuote {
+                    amount_quote,
+                    minimum,
+                });
+            }
+        }
+        Ok(order)
+    }
+}
+
+fn decimal(value: &FloatWrapper) -> Result<Decimal, OrderValidationError> {
+    Decimal::try_from(value).map_err(|_| OrderValidationError::NotDecimal(value.str_repr.clone()))
 }
 
 impl Display for Market {
@@ -36,14 +294,12 @@ impl Display for Market {
                 .map(|o| o.to_string())
                 .get_or_insert(String::from("<empty>")),
             self.min_order_in_quote_asset
-                .as_ref()
                 .map(|o| o.to_string())
                 .get_or_insert(String::from("<empty>")),
             self.min_order_in_base_asset
-                .as_ref()
                 .map(|o| o.to_string())
                 .get_or_insert(String::from("<empty>")),
-            self.order_types.as_ref().unwrap_or(&Vec::<String>::new()),
+            self.order_types.as_deref().unwrap_or_default(),
         )
     }
 }
@@ -60,3 +316,105 @@ impl Display for MarketsResponse {
         write!(f, "Markets: {:?}", self.response,)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bitvavo::OrderRequestBuilder;
+    use crate::order::TriggerReference;
+
+    fn market() -> Market {
+        serde_json::from_value(serde_json::json!({
+            "status": "trading",
+            "base": "BTC",
+            "quote": "EUR",
+            "market": "BTC-EUR",
+            "pricePrecision": 5,
+            "minOrderInQuoteAsset": "5",
+            "minOrderInBaseAsset": "0.0001",
+            "orderTypes": ["market", "limit"],
+        }))
+        .unwrap()
+    }
+
+    fn limit(side: Side, amount: &str, price: &str) -> OrderRequest {
+        OrderRequestBuilder::default()
+            .with_market("BTC-EUR".to_string())
+            .with_side(side)
+            .with_order_type(OrderType::Limit)
+            .with_amount(amount.parse().unwrap())
+            .with_price(price.parse().unwrap())
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn parse_pair() {
+        let pair: Pair = "BTC-EUR".parse().unwrap();
+        assert_eq!(pair, market().pair());
+        assert_eq!(pair.to_string(), "BTC-EUR");
+        for symbol in ["BTCEUR", "-EUR", "BTC-", "A-B-C"] {
+            assert!(symbol.parse::<Pair>().is_err(), "{}", symbol);
+        }
+    }
+
+    #[test]
+    fn unknown_status() {
+        let status: MarketStatus = serde_json::from_str(r#""delisted""#).unwrap();
+        assert_eq!(status, MarketStatus::Unknown);
+        assert_eq!(
+            serde_json::to_string(&MarketStatus::Auction).unwrap(),
+            r#""auction""#
+        );
+    }
+
+    #[test]
+    fn round_price_and_amount() {
+        let market = market();
+        let order = market
+            .validate_order(limit(Side::Buy, "0.123456789", "43210.98"), 8)
+            .unwrap();
+        assert_eq!(order.price.unwrap().str_repr, "43210");
+        assert_eq!(order.amount.unwrap().str_repr, "0.12345678");
+
+        let order = market
+            .validate_order(limit(Side::Sell, "1", "43210.01"), 8)
+            .unwrap();
+        assert_eq!(order.price.unwrap().str_repr, "43211");
+    }
+
+    #[test]
+    fn reject_before_placing() {
+        let market = market();
+        assert_eq!(
+            market
+                .validate_order(limit(Side::Buy, "0.00001", "43210"), 8)
+                .unwrap_err(),
+            OrderValidationError::BelowMinimumBase {
+                amount: "0.00001".parse().unwrap(),
+                minimum: "0.0001".parse().unwrap(),
+            }
+        );
+        assert!(matches!(
+            market.validate_order(limit(Side::Buy, "0.0002", "1000"), 8),
+            Err(OrderValidationError::BelowMinimumQuote { .. })
+        ));
+        assert!(matches!(
+            market.validate_order(limit(Side::Buy, "0.000000001", "43210"), 8),
+            Err(OrderValidationError::AmountRoundsToZero { .. })
+        ));
+
+        let stop_loss = OrderRequestBuilder::default()
+            .with_market("BTC-EUR".to_string())
+            .with_side(Side::Sell)
+            .with_order_type(OrderType::StopLoss)
+            .with_amount("1".parse().unwrap())
+            .with_trigger("40000".parse().unwrap(), TriggerReference::LastTrade)
+            .build()
+            .unwrap();
+        assert_eq!(
+            market.validate_order(stop_loss, 8).unwrap_err(),
+            OrderValidationError::OrderTypeNotAllowed(OrderType::StopLoss)
+        );
+    }
+}
@@ -0,0 +1,209 @@
+use crate::bitvavo::{Bitvavo, BitvavoError};
+use crate::event::Asset;
+use crate::market::{Market, MarketStatus, OrderValidationError, Pair};
+use crate::order::OrderRequest;
+use crate::subscription::{Subscription, SubscriptionError};
+use std::collections::HashMap;
+
+// the markets and assets of the exchange, loaded with getMarkets and getAssets
+// and kept until refreshed
+#[derive(Debug, Default, Clone)]
+pub struct MarketRegistry {
+    markets: HashMap<String, Market>,
+    assets: HashMap<String, Asset>,
+}
+
+impl MarketRegistry {
+    pub fn new(markets: Vec<Market>, assets: Vec<Asset>) -> Self {
+        MarketRegistry {
+            markets: markets
+                .into_iter()
+                .map(|m| (m.market().to_string(), m))
+                .collect(),
+            assets: assets.into_iter().map(|a| (a.symbol.clone(), a)).collect(),
+        }
+    }
+
+    pub async fn load(bitvavo: &Bitvavo) -> Result<Self, BitvavoError> {
+        let markets = bitvavo.get_markets().await?;
+        let assets = bitvavo.get_assets(None).await?;
+        Ok(MarketRegistry::new(markets, assets))
+    }
+
+    // replaces the contents only once both requests succeeded
+    pub async fn refresh(&mut self, bitvavo: &Bitvavo) -> Result<(), BitvavoError> {
+        *self = MarketRegistry::load(bitvavo).await?;
+        Ok(())
+    }
+
+    pub fn market(&self, symbol: &str) -> Option<&Market> {
+        self.markets.get(symbol)
+    }
+
+    pub fn asset(&self, symbol: &str) -> Option<&Asset> {
+        self.assets.get(symbol)
+    }
+
+    pub fn pair(&self, symbol: &str) -> Option<Pair> {
+        self.market(symbol).map(Market::pair)
+    }
+
+    pub fn markets(&self) -> impl Iterator<Item = &Market> {
+        self.markets.values()
+    }
+
+    pub fn assets(&self) -> impl Iterator<Item = &Asset> {
+        self.assets.values()
+    }
+
+    pub fn with_status(&self, status: MarketStatus) -> impl Iterator<Item = &Market> {
+        self.markets().filter(move |m| m.status() == status)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.markets.is_empty()
+    }
+
+    // validates an order against its market, truncating the amount to the
+    // decimals of the base asset; see Market::validate_order
+    pub fn validate_order(
+        &self,
+        order: OrderRequest,
+    ) -> Result<OrderRequest, OrderValidationError> {
+        let Some(market) = self.market(&order.market) else {
+            return Err(OrderValidationError::UnknownMarket(order.market));
+        };
+        if market.status() == MarketStatus::Halted {
+            return Err(OrderValidationError::MarketHalted(order.market));
+        }
+        let Some(base) = self.asset(market.base()) else {
+            return Err(OrderValidationError::UnknownAsset(
+                market.base().to_string(),
+            ));
+        };
+        market.validate_order(order, base.decimals)
+    }
+
+    // the exchange ignores channels for markets it doesn't list
+    pub fn check_subscription(&self, subscription: &Subscription) -> Result<(), SubscriptionError> {
+        match subscription
+            .markets()
+            .into_iter()
+            .find(|market| !self.markets.contains_key(*market))
+        {
+            Some(market) => Err(SubscriptionError::UnknownMarket(market.clone())),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bitvavo::{OrderRequestBuilder, SubscriptionBuilder};
+    use crate::order::OrderType;
+    use crate::side::Side;
+    use serde_json::json;
+
+    fn registry() -> MarketRegistry {
+        let market = |symbol: &str, status: &str| {
+            let (base, quote) = symbol.split_once('-').unwrap();
+            serde_json::from_value(json!({
+                "status": status,
+                "base": base,
+                "quote": quote,
+                "market": symbol,
+                "pricePrecision": 5,
+                "minOrderInQuoteAsset": "5",
+                "minOrderInBaseAsset": "0.001",
+            }))
+            .unwrap()
+        };
+        let asset = |symbol: &str, decimals: u32| {
+            serde_json::from_value(json!({
+                "symbol": symbol,
+                "name": symbol,
+                "decimals": decimals,
+                "depositFee": "0",
+                "depositConfirmations": 10,
+                "depositStatus": "OK",
+                "withdrawalFee": "0.2",
+                "withdrawalMinAmount": "0.2",
+                "withdrawalStatus": "OK",
+            }))
+            .unwrap()
+        };
+        MarketRegistry::new(
+            vec![
+                market("ETH-EUR", "trading"),
+                market("LUNA-EUR", "halted"),
+                market("SOL-EUR", "trading"),
+            ],
+            vec![asset("ETH", 4), asset("LUNA", 8), asset("EUR", 2)],
+        )
+    }
+
+    fn buy(market: &str) -> OrderRequest {
+        OrderRequestBuilder::default()
+            .with_market(market.to_string())
+            .with_side(Side::Buy)
+            .with_order_type(OrderType::Limit)
+            .with_amount("0.123456".parse().unwrap())
+            .with_price("2000".parse().unwrap())
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn lookup_and_validate() {
+        let registry = registry();
+        assert_eq!(registry.with_status(MarketStatus::Trading).count(), 2);
+        assert_eq!(
+            registry.pair("ETH-EUR"),
+            Some(Pair {
+                base: "ETH".to_string(),
+                quote: "EUR".to_string(),
+            })
+        );
+        assert!(registry.market("BTC-EUR").is_none());
+
+        // ETH has 4 decimals
+        let order = registry.validate_order(buy("ETH-EUR")).unwrap();
+        assert_eq!(order.amount.unwrap().str_repr, "0.1234");
+
+        assert_eq!(
+            registry.validate_order(buy("BTC-EUR")).unwrap_err(),
+            OrderValidationError::UnknownMarket("BTC-EUR".to_string())
+        );
+        assert_eq!(
+            registry.validate_order(buy("LUNA-EUR")).unwrap_err(),
+            OrderValidationError::MarketHalted("LUNA-EUR".to_string())
+        );
+        assert_eq!(
+            registry.validate_order(buy("SOL-EUR")).unwrap_err(),
+            OrderValidationError::UnknownAsset("SOL".to_string())
+        );
+    }
+
+    #[test]
+    fn subscribe_to_known_markets() {
+        let registry = registry();
+        let subscription = SubscriptionBuilder::default()
+            .with_known_markets(registry.with_status(MarketStatus::Trading))
+            .with_ticker()
+            .build()
+            .unwrap();
+        assert_eq!(subscription.markets().len(), 2);
+        assert_eq!(registry.check_subscription(&subscription), Ok(()));
+
+        let unknown = SubscriptionBuilder::default()
+            .with_market("BTC-EUR".to_string())
+            .with_ticker()
+            .build()
+            .unwrap();
+        assert_eq!(
+            registry.check_subscription(&unknown),
+            Err(SubscriptionError::UnknownMarket("BTC-EUR".to_string()))
+        );
+    }
+}
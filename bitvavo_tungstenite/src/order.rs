@@ -0,0 +1,636 @@
+use crate::rug_float_serde::FloatWrapper;
+use crate::side::Side;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::fmt::{Display, Formatter};
+
+#[derive(Debug, Eq, PartialEq, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum OrderType {
+    Market,
+    Limit,
+    StopLoss,
+    StopLossLimit,
+    TakeProfit,
+    TakeProfitLimit,
+}
+
+impl OrderType {
+    // the types resting in the book at a limit price
+    pub fn has_limit_price(&self) -> bool {
+        matches!(
+            self,
+            OrderType::Limit | OrderType::StopLossLimit | OrderType::TakeProfitLimit
+        )
+    }
+
+    pub fn has_trigger(&self) -> bool {
+        matches!(
+            self,
+            OrderType::StopLoss
+                | OrderType::StopLossLimit
+                | OrderType::TakeProfit
+                | OrderType::TakeProfitLimit
+        )
+    }
+}
+
+impl Display for OrderType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                OrderType::Market => "market",
+                OrderType::Limit => "limit",
+                OrderType::StopLoss => "stopLoss",
+                OrderType::StopLossLimit => "stopLossLimit",
+                OrderType::TakeProfit => "takeProfit",
+                OrderType::TakeProfitLimit => "takeProfitLimit",
+            }
+        )
+    }
+}
+
+#[derive(Debug, Eq, PartialEq, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum OrderStatus {
+    New,
+    AwaitingTrigger,
+    Canceled,
+    CanceledAuction,
+    CanceledSelfTradePrevention,
+    #[serde(rename = "canceledIOC")]
+    CanceledIoc,
+    #[serde(rename = "canceledFOK")]
+    CanceledFok,
+    CanceledMarketProtection,
+    CanceledPostOnly,
+    Filled,
+    PartiallyFilled,
+    Expired,
+    Rejected,
+}
+
+impl OrderStatus {
+    // whether the order can still trade
+    pub fn is_open(&self) -> bool {
+        matches!(
+            self,
+            OrderStatus::New | OrderStatus::AwaitingTrigger | OrderStatus::PartiallyFilled
+        )
+    }
+}
+
+#[derive(Debug, Eq, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub enum TimeInForce {
+    #[serde(rename = "GTC")]
+    GoodTillCancelled,
+    #[serde(rename = "IOC")]
+    ImmediateOrCancel,
+    #[serde(rename = "FOK")]
+    FillOrKill,
+}
+
+#[derive(Debug, Eq, PartialEq, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SelfTradePrevention {
+    DecrementAndCancel,
+    CancelOldest,
+    CancelNewest,
+    CancelBoth,
+}
+
+#[derive(Debug, Eq, PartialEq, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum TriggerType {
+    Price,
+}
+
+// what the trigger amount of a stop loss or take profit order is compared to
+#[derive(Debug, Eq, PartialEq, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum TriggerReference {
+    LastTrade,
+    BestBid,
+    BestAsk,
+    MidPrice,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum OrderRequestError {
+    MissingMarket,
+    MissingSide,
+    MissingOrderType,
+    MissingAmount,
+    MissingPrice,
+    MissingTrigger,
+    AmountAndAmountQuote,
+    NotPositive(&'static str),
+    // a parameter the exchange doesn't accept for the order type
+    NotApplicable(&'static str, OrderType),
+    PostOnlyWithTimeInForce(TimeInForce),
+    MissingOrderId,
+    NothingToUpdate,
+    AmountAndAmountRemaining,
+}
+
+impl Display for OrderRequestError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OrderRequestError::MissingMarket => write!(f, "no market"),
+            OrderRequestError::MissingSide => write!(f, "no side"),
+            OrderRequestError::MissingOrderType => write!(f, "no order type"),
+            OrderRequestError::MissingAmount => write!(f, "no amount"),
+            OrderRequestError::MissingPrice => write!(f, "no price"),
+            OrderRequestError::MissingTrigger => write!(f, "no trigger"),
+            OrderRequestError::AmountAndAmountQuote => {
+                write!(f, "only one of amount and amountQuote can be set")
+            }
+            OrderRequestError::NotPositive(parameter) => {
+                write!(f, "{} must be positive", parameter)
+            }
+            OrderRequestError::NotApplicable(parameter, order_type) => {
+                write!(
+                    f,
+                    "{} is not supported for {} orders",
+                    parameter, order_type
+                )
+            }
+            OrderRequestError::PostOnlyWithTimeInForce(time_in_force) => {
+                write!(f, "postOnly can't be combined with {:?}", time_in_force)
+            }
+            OrderRequestError::MissingOrderId => write!(f, "no order id"),
+            OrderRequestError::NothingToUpdate => write!(f, "nothing to update"),
+            OrderRequestError::AmountAndAmountRemaining => {
+                write!(f, "only one of amount and amountRemaining can be set")
+            }
+        }
+    }
+}
+
+// a validated privateCreateOrder request, see OrderRequestBuilder
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OrderRequest {
+    pub(crate) market: String,
+    pub(crate) side: Side,
+    pub(crate) order_type: OrderType,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) amount: Option<FloatWrapper>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) amount_quote: Option<FloatWrapper>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) price: Option<FloatWrapper>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) time_in_force: Option<TimeInForce>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub(crate) post_only: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) self_trade_prevention: Option<SelfTradePrevention>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) client_order_id: Option<String>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub(crate) disable_market_protection: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) trigger_amount: Option<FloatWrapper>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) trigger_type: Option<TriggerType>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) trigger_reference: Option<TriggerReference>,
+}
+
+impl OrderRequest {
+    pub fn market(&self) -> &str {
+        &self.market
+    }
+
+    pub fn side(&self) -> Side {
+        self.side
+    }
+
+    pub fn order_type(&self) -> OrderType {
+        self.order_type
+    }
+
+    pub fn client_order_id(&self) -> Option<&str> {
+        self.client_order_id.as_deref()
+    }
+
+    // the combinations the exchange would reject anyway
+    pub(crate) fn validate(self) -> Result<Self, OrderRequestError> {
+        let order_type = self.order_type;
+        for (parameter, value) in [
+            ("amount", &self.amount),
+            ("amountQuote", &self.amount_quote),
+            ("price", &self.price),
+            ("triggerAmount", &self.trigger_amount),
+        ] {
+            if let Some(value) = value
+                && value.float <= 0
+            {
+                return Err(OrderRequestError::NotPositive(parameter));
+            }
+        }
+
+        if order_type.has_limit_price() {
+            if self.amount_quote.is_some() {
+                return Err(OrderRequestError::NotApplicable("amountQuote", order_type));
+            }
+            if self.amount.is_none() {
+                return Err(OrderRequestError::MissingAmount);
+            }
+            if self.price.is_none() {
+                return Err(OrderRequestError::MissingPrice);
+            }
+            if self.disable_market_protection {
+                return Err(OrderRequestError::NotApplicable(
+                    "disableMarketProtection",
+                    order_type,
+                ));
+            }
+            if self.post_only
+                && let Some(time_in_force) = self.time_in_force
+                && time_in_force != TimeInForce::GoodTillCancelled
+            {
+                return Err(OrderRequestError::PostOnlyWithTimeInForce(time_in_force));
+            }
+        } else {
+            match (&self.amount, &self.amount_quote) {
+                (Some(_), Some(_)) => return Err(OrderRequestError::AmountAndAmountQuote),
+                (None, None) => return Err(OrderRequestError::MissingAmount),
+                _ => {}
+            }
+            if self.price.is_some() {
+                return Err(OrderRequestError::NotApplicable("price", order_type));
+            }
+            if self.time_in_force.is_some() {
+                return Err(OrderRequestError::NotApplicable("timeInForce", order_type));
+            }
+            if self.post_only {
+                return Err(OrderRequestError::NotApplicable("postOnly", order_type));
+            }
+        }
+
+        let has_trigger = self.trigger_amount.is_some()
+            || self.trigger_type.is_some()
+            || self.trigger_reference.is_some();
+        if !order_type.has_trigger() && has_trigger {
+            return Err(OrderRequestError::NotApplicable("trigger", order_type));
+        }
+        if order_type.has_trigger()
+            && (self.trigger_amount.is_none()
+                || self.trigger_type.is_none()
+                || self.trigger_reference.is_none())
+        {
+            return Err(OrderRequestError::MissingTrigger);
+        }
+        Ok(self)
+    }
+
+    pub(crate) fn to_message(&self) -> serde_json::Value {
+        let mut message = serde_json::to_value(self).unwrap();
+        message["action"] = json!("privateCreateOrder");
+        message
+    }
+}
+
+// a validated updateOrder request, see OrderUpdateBuilder
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OrderUpdateRequest {
+    pub(crate) market: String,
+    pub(crate) order_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) amount: Option<FloatWrapper>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) amount_remaining: Option<FloatWrapper>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) price: Option<FloatWrapper>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) trigger_amount: Option<FloatWrapper>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) time_in_force: Option<TimeInForce>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) self_trade_prevention: Option<SelfTradePrevention>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) post_only: Option<bool>,
+}
+
+impl OrderUpdateRequest {
+    pub fn market(&self) -> &str {
+        &self.market
+    }
+
+    pub fn order_id(&self) -> &str {
+        &self.order_id
+    }
+
+    pub(crate) fn validate(self) -> Result<Self, OrderRequestError> {
+        for (parameter, value) in [
+            ("amount", &self.amount),
+            ("amountRemaining", &self.amount_remaining),
+            ("price", &self.price),
+            ("triggerAmount", &self.trigger_amount),
+        ] {
+            if let Some(value) = value
+                && value.float <= 0
+            {
+                return Err(OrderRequestError::NotPositive(parameter));
+            }
+        }
+        if self.amount.is_some() && self.amount_remaining.is_some() {
+            return Err(OrderRequestError::AmountAndAmountRemaining);
+        }
+        if self.amount.is_none()
+            && self.amount_remaining.is_none()
+            && self.price.is_none()
+            && self.trigger_amount.is_none()
+            && self.time_in_force.is_none()
+            && self.self_trade_prevention.is_none()
+            && self.post_only.is_none()
+        {
+            return Err(OrderRequestError::NothingToUpdate);
+        }
+        Ok(self)
+    }
+
+    pub(crate) fn to_message(&self) -> serde_json::Value {
+        let mut message = serde_json::to_value(self).unwrap();
+        message["action"] = json!("privateUpdateOrder");
+        message
+    }
+}
+
+// filters for getOrders; the exchange returns the most recent orders first and
+// at most `limit` (500 by default) of them
+#[derive(Debug, Default, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OrdersFilter {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    market: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    limit: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    start: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    end: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    order_id_from: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    order_id_to: Option<String>,
+}
+
+impl OrdersFilter {
+    pub fn with_market(mut self, market: String) -> Self {
+        self.market = Some(market);
+        self
+    }
+
+    pub fn with_limit(mut self, limit: u32) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    // in milliseconds since the epoch
+    pub fn with_start(mut self, start: u64) -> Self {
+        self.start = Some(start);
+        self
+    }
+
+    pub fn with_end(mut self, end: u64) -> Self {
+        self.end = Some(end);
+        self
+    }
+
+    pub fn with_order_ids(mut self, from: String, to: String) -> Self {
+        self.order_id_from = Some(from);
+        self.order_id_to = Some(to);
+        self
+    }
+
+    pub(crate) fn to_message(&self) -> serde_json::Value {
+        let mut message = serde_json::to_value(self).unwrap();
+        message["action"] = json!("privateGetOrders");
+        message
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bitvavo::{OrderRequestBuilder, OrderUpdateBuilder};
+
+    fn float(s: &str) -> FloatWrapper {
+        serde_json::from_value(json!(s)).unwrap()
+    }
+
+    #[test]
+    fn deserialize_order_enums() {
+        let status: OrderStatus = serde_json::from_str("\"canceledIOC\"").unwrap();
+        assert_eq!(status, OrderStatus::CanceledIoc);
+        assert!(!status.is_open());
+
+        let order_type: OrderType = serde_json::from_str("\"stopLossLimit\"").unwrap();
+        assert_eq!(order_type, OrderType::StopLossLimit);
+        assert_eq!(
+            serde_json::to_string(&order_type).unwrap(),
+            format!("\"{}\"", order_type)
+        );
+
+        let time_in_force: TimeInForce = serde_json::from_str("\"FOK\"").unwrap();
+        assert_eq!(time_in_force, TimeInForce::FillOrKill);
+    }
+
+    #[test]
+    fn limit_order_message() {
+        let request = OrderRequestBuilder::default()
+            .with_market("BTC-EUR".to_string())
+            .with_side(Side::Buy)
+            .with_order_type(OrderType::Limit)
+            .with_amount(float("0.5"))
+            .with_price(float("40000"))
+            .with_time_in_force(TimeInForce::GoodTillCancelled)
+            .with_post_only()
+            .with_self_trade_prevention(SelfTradePrevention::CancelOldest)
+            .with_client_order_id("2be7d0df-d8dc-7b93-a550-8876f3b393e9".to_string())
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            request.to_message(),
+            json!({
+                "action": "privateCreateOrder",
+                "market": "BTC-EUR",
+                "side": "buy",
+                "orderType": "limit",
+                "amount": "0.5",
+                "price": "40000",
+                "timeInForce": "GTC",
+                "postOnly": true,
+                "selfTradePrevention": "cancelOldest",
+                "clientOrderId": "2be7d0df-d8dc-7b93-a550-8876f3b393e9",
+            })
+        );
+    }
+
+    #[test]
+    fn stop_loss_message() {
+        let request = OrderRequestBuilder::default()
+            .with_market("BTC-EUR".to_string())
+            .with_side(Side::Sell)
+            .with_order_type(OrderType::StopLoss)
+            .with_amount_quote(float("100"))
+            .with_trigger(float("35000"), TriggerReference::LastTrade)
+            .with_disabled_market_protection()
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            request.to_message(),
+            json!({
+                "action": "privateCreateOrder",
+                "market": "BTC-EUR",
+                "side": "sell",
+                "orderType": "stopLoss",
+                "amountQuote": "100",
+                "disableMarketProtection": true,
+                "triggerAmount": "35000",
+                "triggerType": "price",
+                "triggerReference": "lastTrade",
+            })
+        );
+    }
+
+    #[test]
+    fn reject_invalid_combinations() {
+        let order = |order_type| {
+            OrderRequestBuilder::default()
+                .with_market("BTC-EUR".to_string())
+                .with_side(Side::Buy)
+                .with_order_type(order_type)
+        };
+
+        assert_eq!(
+            OrderRequestBuilder::default().build().unwrap_err(),
+            OrderRequestError::MissingMarket
+        );
+        assert_eq!(
+            order(OrderType::Limit)
+                .with_amount(float("1"))
+                .build()
+                .unwrap_err(),
+            OrderRequestError::MissingPrice
+        );
+        assert_eq!(
+            order(OrderType::Limit)
+                .with_amount(float("1"))
+                .with_price(float("0"))
+                .build()
+                .unwrap_err(),
+            OrderRequestError::NotPositive("price")
+        );
+        assert_eq!(
+            order(OrderType::Market)
+                .with_amount(float("1"))
+                .with_amount_quote(float("1"))
+                .build()
+                .unwrap_err(),
+            OrderRequestError::AmountAndAmountQuote
+        );
+        assert_eq!(
+            order(OrderType::Market)
+                .with_amount(float("1"))
+                .with_time_in_force(TimeInForce::ImmediateOrCancel)
+                .build()
+                .unwrap_err(),
+            OrderRequestError::NotApplicable("timeInForce", OrderType::Market)
+        );
+        assert_eq!(
+            order(OrderType::Limit)
+                .with_amount(float("1"))
+                .with_price(float("1"))
+                .with_post_only()
+                .with_time_in_force(TimeInForce::FillOrKill)
+                .build()
+                .unwrap_err(),
+            OrderRequestError::PostOnlyWithTimeInForce(TimeInForce::FillOrKill)
+        );
+        assert_eq!(
+            order(OrderType::TakeProfitLimit)
+                .with_amount(float("1"))
+                .with_price(float("1"))
+                .build()
+                .unwrap_err(),
+            OrderRequestError::MissingTrigger
+        );
+        assert_eq!(
+            order(OrderType::Limit)
+                .with_amount(float("1"))
+                .with_price(float("1"))
+                .with_trigger(float("1"), TriggerReference::BestBid)
+                .build()
+                .unwrap_err(),
+            OrderRequestError::NotApplicable("trigger", OrderType::Limit)
+        );
+    }
+
+    #[test]
+    fn update_order_message() {
+        let update = OrderUpdateBuilder::default()
+            .with_market("BTC-EUR".to_string())
+            .with_order_id("1be6d0df".to_string())
+            .with_amount_remaining(float("0.25"))
+            .with_price(float("39000"))
+            .build()
+            .unwrap();
+        assert_eq!(
+            update.to_message(),
+            json!({
+                "action": "privateUpdateOrder",
+                "market": "BTC-EUR",
+                "orderId": "1be6d0df",
+                "amountRemaining": "0.25",
+                "price": "39000",
+            })
+        );
+
+        let update = || {
+            OrderUpdateBuilder::default()
+                .with_market("BTC-EUR".to_string())
+                .with_order_id("1be6d0df".to_string())
+        };
+        assert_eq!(
+            update().build().unwrap_err(),
+            OrderRequestError::NothingToUpdate
+        );
+        assert_eq!(
+            update()
+                .with_amount(float("1"))
+                .with_amount_remaining(float("1"))
+                .build()
+                .unwrap_err(),
+            OrderRequestError::AmountAndAmountRemaining
+        );
+    }
+
+    #[test]
+    fn orders_filter_message() {
+        let filter = OrdersFilter::default()
+            .with_market("BTC-EUR".to_string())
+            .with_limit(100)
+            .with_start(1700000000000)
+            .with_order_ids("a".to_string(), "b".to_string());
+        assert_eq!(
+            filter.to_message(),
+            json!({
+                "action": "privateGetOrders",
+                "market": "BTC-EUR",
+                "limit": 100,
+                "start": 1700000000000u64,
+                "orderIdFrom": "a",
+                "orderIdTo": "b",
+            })
+        );
+    }
+}
@@ -1,13 +1,13 @@
-use crate::rug_float_serde::FloatWrapper;
+use crate::decimal::Decimal;
 use serde::de::{SeqAccess, Visitor};
-use serde::{de, Deserialize, Deserializer, Serialize};
+use serde::{Deserialize, Deserializer, Serialize, de};
 use serde_json::from_value;
 use std::fmt::{Debug, Formatter};
 
 #[derive(Clone, Serialize, Default)]
 pub struct PriceLevel {
-    pub price: FloatWrapper,
-    pub quantity: FloatWrapper,
+    pub price: Decimal,
+    pub quantity: Decimal,
 }
 
 impl Debug for PriceLevel {
@@ -18,7 +18,8 @@ impl Debug for PriceLevel {
 
 #[derive(Serialize, Deserialize, Clone, Debug, Default)]
 pub struct Book {
-    pub nonce: i32,
+    pub market: String,
+    pub nonce: u64,
     pub bids: Vec<PriceLevel>,
     pub asks: Vec<PriceLevel>,
 }
@@ -47,9 +48,8 @@ impl<'de> Deserialize<'de> for PriceLevel {
                 let quantity_str = seq
                     .next_element()?
                     .ok_or_else(|| de::Error::invalid_length(1, &self))?;
-                let price = from_value::<FloatWrapper>(price_str).map_err(de::Error::custom)?;
-                let quantity =
-                    from_value::<FloatWrapper>(quantity_str).map_err(de::Error::custom)?;
+                let price = from_value::<Decimal>(price_str).map_err(de::Error::custom)?;
+                let quantity = from_value::<Decimal>(quantity_str).map_err(de::Error::custom)?;
 
                 Ok(PriceLevel { price, quantity })
             }
@@ -1,10 +1,17 @@
-use rug::float::Round;
-use rug::float::Round::Nearest;
+use crate::decimal::{Decimal, ParseDecimalError};
 use rug::Float;
-use serde::de::{self, Deserialize, Deserializer, Visitor};
+use rug::float::Round::Nearest;
 use serde::Serialize;
+use serde::de::{self, Deserialize, Deserializer, Visitor};
+use std::cmp::Ordering;
 use std::fmt;
 use std::fmt::{Debug, Display};
+use std::iter::Sum;
+use std::ops::{Add, Div, Mul, Sub};
+use std::str::FromStr;
+
+// the precision of everything parsed or converted, that of an f64
+pub const PRECISION: u32 = 53;
 
 #[derive(Clone)]
 pub struct FloatWrapper {
@@ -19,13 +26,171 @@ impl FloatWrapper {
     }
 }
 
+// the shortest string parsing back to the same value, in plain notation up to
+// PRECISION bits
+fn repr(float: &Float) -> String {
+    if float.prec() <= PRECISION {
+        float.to_f64().to_string()
+    } else {
+        float.to_string_radix(10, None)
+    }
+}
+
 impl From<Float> for FloatWrapper {
     fn from(float: Float) -> Self {
-        let str_repr = float.to_string_radix_round(10, Some(10), Round::Up);
+        let str_repr = repr(&float);
         FloatWrapper { float, str_repr }
     }
 }
 
+impl From<f64> for FloatWrapper {
+    fn from(value: f64) -> Self {
+        FloatWrapper::from(Float::with_val(PRECISION, value))
+    }
+}
+
+// integers beyond 2^53 are rounded, like they would be as an f64
+macro_rules! from_integer {
+    ($($t:ty),*) => {
+        $(
+            impl From<$t> for FloatWrapper {
+                fn from(value: $t) -> Self {
+                    FloatWrapper::from(Float::with_val(PRECISION, value))
+                }
+            }
+        )*
+    };
+}
+
+from_integer!(i32, i64, u32, u64);
+
+impl FromStr for FloatWrapper {
+    type Err = rug::float::ParseFloatError;
+
+    // keeps the string as given, like deserializing does
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Float::parse(s).map(|parsed| FloatWrapper {
+            float: Float::with_val(PRECISION, parsed),
+            str_repr: s.to_owned(),
+        })
+    }
+}
+
+impl From<Decimal> for FloatWrapper {
+    fn from(value: Decimal) -> Self {
+        let str_repr = value.to_string();
+        FloatWrapper {
+            float: Float::with_val(PRECISION, Float::parse(&str_repr).unwrap()),
+            str_repr,
+        }
+    }
+}
+
+// exact, as long as the string form is plain decimal notation
+impl TryFrom<&FloatWrapper> for Decimal {
+    type Error = ParseDecimalError;
+
+    fn try_from(value: &FloatWrapper) -> Result<Self, Self::Error> {
+        value.str_repr.parse()
+    }
+}
+
+impl From<&FloatWrapper> for f64 {
+    fn from(value: &FloatWrapper) -> Self {
+        value.float.to_f64()
+    }
+}
+
+impl FloatWrapper {
+    pub fn to_f64(&self) -> f64 {
+        self.float.to_f64()
+    }
+
+    // None unless the value is an integer within range
+    pub fn to_i64(&self) -> Option<i64> {
+        self.float
+            .to_integer()
+            .filter(|_| self.float.is_integer())?
+            .to_i64()
+    }
+
+    pub fn to_u64(&self) -> Option<u64> {
+        self.float
+            .to_integer()
+            .filter(|_| self.float.is_integer())?
+            .to_u64()
+    }
+}
+
+impl PartialEq for FloatWrapper {
+    fn eq(&self, other: &Self) -> bool {
+        self.float == other.float
+    }
+}
+
+impl PartialOrd for FloatWrapper {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        self.float.partial_cmp(&other.float)
+    }
+}
+
+macro_rules! operator {
+    ($trait:ident, $method:ident) => {
+        impl $trait<&FloatWrapper> for &FloatWrapper {
+            type Output = FloatWrapper;
+
+            fn $method(self, other: &FloatWrapper) -> FloatWrapper {
+                let precision = self.float.prec().max(other.float.prec());
+                FloatWrapper::from(Float::with_val(
+                    precision,
+                    (&self.float).$method(&other.float),
+                ))
+            }
+        }
+
+        impl $trait for FloatWrapper {
+            type Output = FloatWrapper;
+
+            fn $method(self, other: FloatWrapper) -> FloatWrapper {
+                (&self).$method(&other)
+            }
+        }
+
+        impl $trait<&FloatWrapper> for FloatWrapper {
+            type Output = FloatWrapper;
+
+            fn $method(self, other: &FloatWrapper) -> FloatWrapper {
+                (&self).$method(other)
+            }
+        }
+
+        impl $trait<FloatWrapper> for &FloatWrapper {
+            type Output = FloatWrapper;
+
+            fn $method(self, other: FloatWrapper) -> FloatWrapper {
+                self.$method(&other)
+            }
+        }
+    };
+}
+
+operator!(Add, add);
+operator!(Sub, sub);
+operator!(Mul, mul);
+operator!(Div, div);
+
+impl Sum for FloatWrapper {
+    fn sum<I: Iterator<Item = FloatWrapper>>(iter: I) -> Self {
+        iter.fold(FloatWrapper::default(), |acc, value| acc + value)
+    }
+}
+
+impl<'a> Sum<&'a FloatWrapper> for FloatWrapper {
+    fn sum<I: Iterator<Item = &'a FloatWrapper>>(iter: I) -> Self {
+        iter.fold(FloatWrapper::default(), |acc, value| acc + value)
+    }
+}
+
 impl Debug for FloatWrapper {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
         fmt.write_str(&self.str_repr)
@@ -34,10 +199,7 @@ impl Debug for FloatWrapper {
 
 impl Default for FloatWrapper {
     fn default() -> Self {
-        FloatWrapper {
-            float: Float::new(8),
-            str_repr: "X".to_owned(),
-        }
+        FloatWrapper::from(Float::new(PRECISION))
     }
 }
 
@@ -73,7 +235,7 @@ impl<'de> Deserialize<'de> for FloatWrapper {
                 Float::parse(value)
                     .map_err(|err| de::Error::custom(format!("failed to parse Float: {}", err)))
                     .map(|parse_incomplete| FloatWrapper {
-                        float: Float::with_val(53, parse_incomplete),
+                        float: Float::with_val(PRECISION, parse_incomplete),
                         str_repr: value.to_owned(),
                     })
             }
@@ -108,4 +270,39 @@ mod tests {
         assert_eq!("100.50", float.str_repr);
         assert_eq!(Float::with_val(10, 100.5), float.float);
     }
+
+    fn f(s: &str) -> FloatWrapper {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn arithmetic_keeps_repr_consistent() {
+        let sum = f("0.1") + f("0.2");
+        assert_eq!(sum.str_repr, "0.30000000000000004");
+        assert_eq!(sum.str_repr.parse::<f64>().unwrap(), sum.to_f64());
+
+        let a = f("101");
+        let b = f("99");
+        assert_eq!((&a - &b).str_repr, "2");
+        assert_eq!((&a * &b).str_repr, "9999");
+        assert_eq!((f("1") / f("4")).str_repr, "0.25");
+        assert_eq!(
+            (a.clone() + &b) / FloatWrapper::from(2),
+            FloatWrapper::from(100)
+        );
+        assert_eq!(FloatWrapper::default().str_repr, "0");
+    }
+
+    #[test]
+    fn compare_and_sum() {
+        assert_eq!(f("100.50"), f("100.5"));
+        assert!(f("0.1") < f("0.11"));
+        assert!(f("-1") < FloatWrapper::from(0u32));
+
+        let total: FloatWrapper = [f("1.5"), f("2.25"), f("0.25")].iter().sum();
+        assert_eq!(total, FloatWrapper::from(4i64));
+        assert_eq!(total.to_u64(), Some(4));
+        assert_eq!(f("2.5").to_i64(), None);
+        assert_eq!(f64::from(&f("2.5")), 2.5);
+    }
 }
@@ -1,7 +1,7 @@
 use serde::{Deserialize, Serialize};
 use std::fmt::{Display, Formatter};
 
-#[derive(Debug, Eq, PartialEq, Clone, Serialize, Deserialize)]
+#[derive(Debug, Eq, PartialEq, Clone, Copy, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum Side {
     Buy,
@@ -37,7 +37,6 @@ mod tests {
         assert_eq!(serialized.unwrap().as_str(), "\"sell\"");
     }
 
-    use super::*;
     #[test]
     fn deserialize_side_buy() {
         let buy = Side::Buy;
@@ -0,0 +1,296 @@
+use crate::candle::Interval;
+use serde_json::json;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fmt::{Display, Formatter};
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Channel {
+    Trades,
+    Account,
+    Book,
+    Ticker,
+    Candles(Interval),
+}
+
+impl Channel {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Channel::Trades => "trades",
+            Channel::Account => "account",
+            Channel::Book => "book",
+            Channel::Ticker => "ticker",
+            Channel::Candles(_) => "candles",
+        }
+    }
+
+    fn to_json(&self, markets: &BTreeSet<String>) -> serde_json::Value {
+        match self {
+            Channel::Candles(interval) => json!({
+                "name": self.name(),
+                "interval": [ interval.as_str() ],
+                "markets": markets,
+            }),
+            _ => json!({
+                "name": self.name(),
+                "markets": markets,
+            }),
+        }
+    }
+}
+
+impl Display for Channel {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Channel::Candles(interval) => write!(f, "candles({})", interval),
+            channel => write!(f, "{}", channel.name()),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum SubscriptionError {
+    NoMarkets(Channel),
+    NoChannels,
+    // see MarketRegistry::check_subscription
+    UnknownMarket(String),
+}
+
+impl Display for SubscriptionError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SubscriptionError::NoMarkets(channel) => write!(f, "no markets for {}", channel),
+            SubscriptionError::NoChannels => write!(f, "no channels"),
+            SubscriptionError::UnknownMarket(market) => write!(f, "unknown market {}", market),
+        }
+    }
+}
+
+// a validated set of channels and the markets for each of them, sent as a
+// single subscribe or unsubscribe message
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Subscription {
+    channels: BTreeMap<Channel, BTreeSet<String>>,
+}
+
+impl Subscription {
+    pub(crate) fn new(
+        channels: BTreeMap<Channel, BTreeSet<String>>,
+    ) -> Result<Self, SubscriptionError> {
+        if channels.is_empty() {
+            return Err(SubscriptionError::NoChannels);
+        }
+        for (channel, markets) in &channels {
+            if markets.is_empty() {
+                return Err(SubscriptionError::NoMarkets(channel.clone()));
+            }
+        }
+        Ok(Subscription { channels })
+    }
+
+    pub fn channels(&self) -> &BTreeMap<Channel, BTreeSet<String>> {
+        &self.channels
+    }
+
+    pub fn markets(&self) -> BTreeSet<&String> {
+        self.channels.values().flatten().collect()
+    }
+
+    pub(crate) fn to_message(&self, action: &str) -> serde_json::Value {
+        let channels = self
+            .channels
+            .iter()
+            .map(|(channel, markets)| channel.to_json(markets))
+            .collect::<Vec<_>>();
+        json!({
+            "action": action,
+            "channels": channels,
+        })
+    }
+}
+
+// active channels per market, as far as the client is concerned: updated when a
+// subscribe or unsubscribe has been sent
+#[derive(Debug, Default, Clone)]
+pub struct SubscriptionRegistry {
+    markets: HashMap<String, HashSet<Channel>>,
+}
+
+impl SubscriptionRegistry {
+    pub fn add(&mut self, subscription: &Subscription) {
+        for (channel, markets) in subscription.channels() {
+            for market in markets {
+                self.markets
+                    .entry(market.clone())
+                    .or_default()
+                    .insert(channel.clone());
+            }
+        }
+    }
+
+    pub fn remove(&mut self, subscription: &Subscription) {
+        for (channel, markets) in subscription.channels() {
+            for market in markets {
+                if let Some(channels) = self.markets.get_mut(market) {
+                    channels.remove(channel);
+                    if channels.is_empty() {
+                        self.markets.remove(market);
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn is_subscribed(&self, market: &str, channel: &Channel) -> bool {
+        self.markets
+            .get(market)
+            .is_some_and(|channels| channels.contains(channel))
+    }
+
+    pub fn channels(&self, market: &str) -> Option<&HashSet<Channel>> {
+        self.markets.get(market)
+    }
+
+    pub fn markets(&self) -> impl Iterator<Item = &String> {
+        self.markets.keys()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.markets.is_empty()
+    }
+
+    // everything that is active, as one subscription; None when nothing is
+    pub fn to_subscription(&self) -> Option<Subscription> {
+        let mut channels = BTreeMap::<Channel, BTreeSet<String>>::new();
+        for (market, market_channels) in &self.markets {
+            for channel in market_channels {
+                channels
+                    .entry(channel.clone())
+                    .or_default()
+                    .insert(market.clone());
+            }
+        }
+        Subscription::new(channels).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bitvavo::SubscriptionBuilder;
+
+    #[test]
+    fn add_and_remove_channels() {
+        let mut registry = SubscriptionRegistry::default();
+        registry.add(
+            &SubscriptionBuilder::default()
+                .with_market("BTC-EUR".to_string())
+                .with_ticker()
+                .with_candles(Interval::OneMinute)
+                .build()
+                .unwrap(),
+        );
+        registry.add(
+            &SubscriptionBuilder::default()
+                .with_market("ETH-EUR".to_string())
+                .with_book()
+                .build()
+                .unwrap(),
+        );
+        assert!(registry.is_subscribed("BTC-EUR", &Channel::Ticker));
+        assert!(registry.is_subscribed("BTC-EUR", &Channel::Candles(Interval::OneMinute)));
+        assert!(!registry.is_subscribed("BTC-EUR", &Channel::Book));
+
+        registry.remove(
+            &SubscriptionBuilder::default()
+                .with_market("BTC-EUR".to_string())
+                .with_ticker()
+                .build()
+                .unwrap(),
+        );
+        assert!(!registry.is_subscribed("BTC-EUR", &Channel::Ticker));
+        assert!(registry.is_subscribed("BTC-EUR", &Channel::Candles(Interval::OneMinute)));
+
+        registry.remove(
+            &SubscriptionBuilder::default()
+                .with_market("ETH-EUR".to_string())
+                .with_book()
+                .build()
+                .unwrap(),
+        );
+        assert!(registry.channels("ETH-EUR").is_none());
+        assert_eq!(registry.markets().count(), 1);
+    }
+
+    #[test]
+    fn registry_to_subscription_covers_active_channels() {
+        let mut registry = SubscriptionRegistry::default();
+        assert!(registry.to_subscription().is_none());
+
+        registry.add(
+            &SubscriptionBuilder::default()
+                .with_markets(["BTC-EUR".to_string(), "ETH-EUR".to_string()])
+                .with_trades()
+                .build()
+                .unwrap(),
+        );
+        registry.add(
+            &SubscriptionBuilder::default()
+                .with_market("BTC-EUR".to_string())
+                .with_account()
+                .build()
+                .unwrap(),
+        );
+
+        let subscription = registry.to_subscription().unwrap();
+        assert_eq!(subscription.channels().len(), 2);
+        assert_eq!(subscription.channels()[&Channel::Trades].len(), 2);
+        assert_eq!(subscription.channels()[&Channel::Account].len(), 1);
+    }
+
+    #[test]
+    fn combined_message() {
+        let subscription = SubscriptionBuilder::default()
+            .with_markets(["BTC-EUR".to_string(), "ETH-EUR".to_string()])
+            .with_ticker()
+            .with_candles(Interval::OneMinute)
+            .with_candles(Interval::OneHour)
+            .with_channel_for(Channel::Book, ["SOL-EUR".to_string()])
+            .build()
+            .unwrap();
+
+        let message = subscription.to_message("subscribe");
+        let channels = message["channels"].as_array().unwrap();
+        assert_eq!(message["action"], "subscribe");
+        assert_eq!(channels.len(), 4);
+        assert!(channels.contains(&json!({
+            "name": "ticker",
+            "markets": ["BTC-EUR", "ETH-EUR"],
+        })));
+        assert!(channels.contains(&json!({
+            "name": "candles",
+            "interval": ["1h"],
+            "markets": ["BTC-EUR", "ETH-EUR"],
+        })));
+        assert!(channels.contains(&json!({
+            "name": "book",
+            "markets": ["SOL-EUR"],
+        })));
+    }
+
+    #[test]
+    fn build_rejects_invalid_subscriptions() {
+        // unsupported candle intervals don't parse in the first place
+        assert!("3m".parse::<Interval>().is_err());
+
+        let no_markets = SubscriptionBuilder::default().with_ticker().build();
+        assert_eq!(
+            no_markets,
+            Err(SubscriptionError::NoMarkets(Channel::Ticker))
+        );
+
+        let no_channels = SubscriptionBuilder::default()
+            .with_market("BTC-EUR".to_string())
+            .build();
+        assert_eq!(no_channels, Err(SubscriptionError::NoChannels));
+    }
+}
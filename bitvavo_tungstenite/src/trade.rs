@@ -1,24 +1,57 @@
-use crate::rug_float_serde::FloatWrapper;
+use crate::decimal::Decimal;
 use crate::side::Side;
 use serde::{Deserialize, Serialize};
 use std::fmt;
 use std::fmt::{Display, Formatter};
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+// a trade event, or a row of a getTrades response; the rows don't carry the
+// market, Bitvavo::get_trades fills it in
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct Trade {
-    timestamp: u64,
-    id: String,
-    amount: FloatWrapper,
-    price: FloatWrapper,
-    side: Side,
+    #[serde(default)]
+    pub market: String,
+    // milliseconds since the epoch, as stamped by the exchange
+    pub timestamp: u64,
+    pub id: String,
+    pub amount: Decimal,
+    pub price: Decimal,
+    // the taker's side
+    pub side: Side,
+}
+
+// the same as the fields, for callers written against the accessors
+impl Trade {
+    pub fn market(&self) -> &str {
+        &self.market
+    }
+
+    pub fn timestamp(&self) -> u64 {
+        self.timestamp
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn amount(&self) -> Decimal {
+        self.amount
+    }
+
+    pub fn price(&self) -> Decimal {
+        self.price
+    }
+
+    pub fn side(&self) -> Side {
+        self.side
+    }
 }
 
 impl Display for Trade {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         write!(
             f,
-            "Trade [timestamp: {}, id: {}, amount: {}, price: {}, side: {}]",
-            self.timestamp, self.id, self.amount, self.price, self.side
+            "Trade [market: {}, timestamp: {}, id: {}, amount: {}, price: {}, side: {}]",
+            self.market, self.timestamp, self.id, self.amount, self.price, self.side
         )
     }
 }
@@ -30,6 +63,8 @@ mod tests {
     #[test]
     fn decode_trade() {
         let s = serde_json::json!({
+            "event": "trade",
+            "market": "BTC-EUR",
             "timestamp": 123123123,
             "id": "123",
             "amount": "123",
@@ -37,6 +72,8 @@ mod tests {
             "side": "sell",
         });
 
-        serde_json::from_value::<Trade>(s).unwrap();
+        let trade = serde_json::from_value::<Trade>(s).unwrap();
+        assert_eq!(trade.market, "BTC-EUR");
+        assert_eq!(trade.side, Side::Sell);
     }
 }
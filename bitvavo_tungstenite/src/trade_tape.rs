@@ -0,0 +1,194 @@
+use crate::decimal::Decimal;
+use crate::side::Side;
+use crate::trade::Trade;
+use std::collections::{HashMap, VecDeque};
+use std::time::Duration;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TapeStats {
+    pub trades: usize,
+    pub volume: Decimal,
+    // by the taker's side
+    pub buy_volume: Decimal,
+    pub sell_volume: Decimal,
+    pub vwap: Decimal,
+    pub largest: Trade,
+}
+
+impl TapeStats {
+    // between -1 (only sells) and 1 (only buys)
+    pub fn imbalance(&self) -> Decimal {
+        (self.buy_volume - self.sell_volume) / self.volume
+    }
+}
+
+// the trades of the last `window` per market; the exchange timestamps are the
+// clock, so a market without trades empties out as the others move on
+#[derive(Debug, Clone)]
+pub struct TradeTape {
+    window: u64,
+    now: u64,
+    markets: HashMap<String, VecDeque<Trade>>,
+}
+
+impl TradeTape {
+    pub fn new(window: Duration) -> Self {
+        TradeTape {
+            window: window.as_millis() as u64,
+            now: 0,
+            markets: HashMap::new(),
+        }
+    }
+
+    pub fn ingest(&mut self, trade: Trade) {
+        self.advance(trade.timestamp);
+        if self.in_window(&trade) {
+            let trades = self.markets.entry(trade.market.clone()).or_default();
+            // trades mostly arrive in order, a late one is put in its place
+            let at = trades.partition_point(|t| t.timestamp <= trade.timestamp);
+            trades.insert(at, trade);
+        }
+    }
+
+    // moves the clock without a trade, e.g. to the exchange time
+    pub fn advance(&mut self, timestamp: u64) {
+        self.now = self.now.max(timestamp);
+        let start = self.window_start();
+        self.markets.retain(|_, trades| {
+            while trades.front().is_some_and(|t| t.timestamp < start) {
+                trades.pop_front();
+            }
+            !trades.is_empty()
+        });
+    }
+
+    // oldest first
+    pub fn trades(&self, market: &str) -> impl Iterator<Item = &Trade> {
+        self.markets.get(market).into_iter().flatten()
+    }
+
+    pub fn markets(&self) -> impl Iterator<Item = &String> {
+        self.markets.keys()
+    }
+
+    pub fn trade_count(&self, market: &str) -> usize {
+        self.markets.get(market).map_or(0, VecDeque::len)
+    }
+
+    pub fn vwap(&self, market: &str) -> Option<Decimal> {
+        self.stats(market).map(|stats| stats.vwap)
+    }
+
+    pub fn imbalance(&self, market: &str) -> Option<Decimal> {
+        self.stats(market).map(|stats| stats.imbalance())
+    }
+
+    // the trade with the largest amount, the earliest one on a tie
+    pub fn largest_print(&self, market: &str) -> Option<&Trade> {
+        self.trades(market).reduce(|largest, t| {
+            if t.amount > largest.amount {
+                t
+            } else {
+                largest
+            }
+        })
+    }
+
+    // None when the market had no trades within the window
+    pub fn stats(&self, market: &str) -> Option<TapeStats> {
+        let largest = self.largest_print(market)?.clone();
+        let mut stats = TapeStats {
+            trades: 0,
+            volume: Decimal::ZERO,
+            buy_volume: Decimal::ZERO,
+            sell_volume: Decimal::ZERO,
+            vwap: Decimal::ZERO,
+            largest,
+        };
+        let mut notional = Decimal::ZERO;
+        for trade in self.trades(market) {
+            stats.trades += 1;
+            stats.volume += trade.amount;
+            match trade.side {
+                Side::Buy => stats.buy_volume += trade.amount,
+                Side::Sell => stats.sell_volume += trade.amount,
+            }
+            notional += trade.amount * trade.price;
+        }
+        if stats.volume.is_zero() {
+            return None;
+        }
+        stats.vwap = notional / stats.volume;
+        Some(stats)
+    }
+
+    fn window_start(&self) -> u64 {
+        self.now.saturating_sub(self.window)
+    }
+
+    fn in_window(&self, trade: &Trade) -> bool {
+        trade.timestamp >= self.window_start()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trade(market: &str, timestamp: u64, side: Side, amount: &str, price: &str) -> Trade {
+        Trade {
+            market: market.to_string(),
+            timestamp,
+            id: timestamp.to_string(),
+            amount: amount.parse().unwrap(),
+            price: price.parse().unwrap(),
+            side,
+        }
+    }
+
+    fn d(s: &str) -> Decimal {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn windowed_analytics() {
+        let mut tape = TradeTape::new(Duration::from_secs(60));
+        tape.ingest(trade("BTC-EUR", 1_000, Side::Buy, "1", "100"));
+        tape.ingest(trade("BTC-EUR", 20_000, Side::Sell, "3", "104"));
+        tape.ingest(trade("ETH-EUR", 30_000, Side::Buy, "10", "5"));
+        tape.ingest(trade("BTC-EUR", 40_000, Side::Buy, "4", "101"));
+
+        let stats = tape.stats("BTC-EUR").unwrap();
+        assert_eq!(stats.trades, 3);
+        assert_eq!(stats.volume, d("8"));
+        // (100 + 312 + 404) / 8
+        assert_eq!(stats.vwap, d("102"));
+        // (5 - 3) / 8
+        assert_eq!(stats.imbalance(), d("0.25"));
+        assert_eq!(stats.largest.id, "40000");
+
+        // the first trade falls out of the window
+        tape.ingest(trade("BTC-EUR", 61_500, Side::Sell, "1", "100"));
+        assert_eq!(tape.trade_count("BTC-EUR"), 3);
+        assert_eq!(tape.imbalance("BTC-EUR"), Some(Decimal::ZERO));
+    }
+
+    #[test]
+    fn quiet_markets_empty_out() {
+        let mut tape = TradeTape::new(Duration::from_secs(10));
+        tape.ingest(trade("ETH-EUR", 1_000, Side::Buy, "1", "5"));
+        tape.ingest(trade("BTC-EUR", 5_000, Side::Buy, "1", "100"));
+        // late, but within the window
+        tape.ingest(trade("BTC-EUR", 2_000, Side::Sell, "2", "99"));
+        assert_eq!(tape.trades("BTC-EUR").next().unwrap().timestamp, 2_000);
+
+        tape.advance(11_500);
+        assert!(tape.stats("ETH-EUR").is_none());
+        assert_eq!(tape.markets().count(), 1);
+        assert_eq!(tape.largest_print("BTC-EUR").unwrap().amount, d("2"));
+
+        // older than the window
+        tape.ingest(trade("BTC-EUR", 500, Side::Buy, "5", "100"));
+        assert_eq!(tape.trade_count("BTC-EUR"), 2);
+    }
+}
@@ -1,12 +1,17 @@
-use bitvavo_tungstenite::bitvavo::{Bitvavo, SubscriptionBuilder};
-use bitvavo_tungstenite::decode::decode_event;
-use bitvavo_tungstenite::event::{AuthRequest, BitvavoEvent};
-use bitvavo_tungstenite::local_book::LocalBook;
+use bitvavo_tungstenite::bitvavo::SubscriptionBuilder;
+use bitvavo_tungstenite::book_manager::BookManager;
+use bitvavo_tungstenite::candle::Interval;
+use bitvavo_tungstenite::connection::{
+    ConnectionEvent, ManagedConnection, ManagedConnectionBuilder,
+};
+use bitvavo_tungstenite::decimal::Decimal;
+use bitvavo_tungstenite::event::BitvavoEvent;
+use bitvavo_tungstenite::local_book::BookSync;
+use bitvavo_tungstenite::market::MarketStatus;
+use bitvavo_tungstenite::market_registry::MarketRegistry;
+use bitvavo_tungstenite::trade_tape::TradeTape;
 use clap::Parser;
-use futures_util::StreamExt;
-use std::collections::HashMap;
-use tokio_tungstenite::connect_async;
-use tungstenite::client::IntoClientRequest;
+use std::time::Duration;
 
 #[derive(Parser, Debug)]
 #[clap(
@@ -31,86 +36,126 @@ struct Config {
     pub quote_asset: String,
 }
 
+// a book that can't catch up from its updates needs a (new) snapshot
+async fn resync(connection: &ManagedConnection, market: &str, sync: BookSync) {
+    if sync == BookSync::NeedsSnapshot
+        && let Some(bitvavo) = connection.client()
+    {
+        bitvavo
+            .request_book(market)
+            .await
+            .expect("failed to request the book");
+    }
+}
+
 #[tokio::main]
 async fn main() {
     env_logger::init();
     let config = Config::parse();
     let market_symbol = format!("{}-{}", &config.base_asset, &config.quote_asset);
 
-    let url = config.ws_url.into_client_request().unwrap();
-    let (ws_stream, _) = connect_async(url).await.expect("failed to connect");
-    let (write, mut read) = ws_stream.split();
-
-    // wrapping write Sink into the Bitvavo struct to send commands
-    let mut bitvavo = Bitvavo::wrap(write);
-
-    log::info!("requesting authentication");
-    bitvavo
-        .authenticate(AuthRequest::make(&config.api_key, &config.api_secret))
-        .await
-        .expect("failed request authentication");
-
-    // log::info!("now waiting for the authentication to be complete");
-    //
-    // let msg = read
-    //     .next()
-    //     .await
-    //     .expect("error authentication response reading message");
-
-    // log::info!("authentication succeeded: {}", msg.unwrap());
+    // the managed connection owns the socket: it reconnects, authenticates and
+    // replays the subscriptions on its own, reporting the transitions as events
+    let (connection, mut events) = ManagedConnectionBuilder::default()
+        .with_url(config.ws_url)
+        .with_credentials(config.api_key, config.api_secret)
+        .connect();
 
     // now we can call actions and receive events/updates
-    let mut local_book = LocalBook::default();
-    let mut balances = HashMap::default();
-
-    log::info!("requesting balances...");
-    bitvavo
-        .get_balances()
-        .await
-        .expect("failed to request balances");
-
-    log::info!("requesting subscription...");
-    let sb = SubscriptionBuilder::default()
-        .with_market(market_symbol)
-        .with_ticker()
-        .with_account()
-        .with_trades()
-        .with_candles("1m");
-
-    bitvavo.subscribe(sb).await.expect("failed to subscribe");
+    let mut subscribed = false;
+    let mut books = BookManager::default();
+    let mut registry = MarketRegistry::default();
+    let mut tape = TradeTape::new(Duration::from_secs(60));
 
     log::info!("starting polling market data...");
-    loop {
-        let msg = read.next().await;
-        match msg {
-            Some(Ok(tungstenite::Message::Close(_))) => {
+    while let Some(event) = events.recv().await {
+        match event {
+            ConnectionEvent::Connected => {
+                log::info!("connected, loading markets...");
+                let bitvavo = connection.client().expect("not connected");
+                // the last known markets stay around when the refresh fails
+                if let Err(e) = registry.refresh(&bitvavo).await {
+                    log::error!("failed to load markets: {:?}", e);
+                }
+                match registry.market(&market_symbol) {
+                    Some(market) if market.status() == MarketStatus::Trading => {
+                        log::info!("{}", market)
+                    }
+                    Some(market) => log::warn!("{} is {}", market_symbol, market.status()),
+                    None => log::error!("{} is not listed", market_symbol),
+                }
+                // once the market is known to exist; the connection replays the
+                // subscription after a reconnect
+                if !subscribed && let Some(market) = registry.market(&market_symbol) {
+                    log::info!("requesting subscription...");
+                    let sb = SubscriptionBuilder::default()
+                        .with_known_market(market)
+                        .with_ticker()
+                        .with_account()
+                        .with_trades()
+                        .with_candles(Interval::OneMinute)
+                        .build()
+                        .expect("invalid subscription");
+                    registry
+                        .check_subscription(&sb)
+                        .expect("invalid subscription");
+                    connection.subscribe(sb).await.expect("failed to subscribe");
+                    subscribed = true;
+                }
+
+                log::info!("requesting balances...");
+                let balances = bitvavo
+                    .get_balances()
+                    .await
+                    .expect("failed to request balances");
+                log::info!("balances: {:?}", balances);
+            }
+            ConnectionEvent::Disconnected => {
                 log::error!("server closed the connection");
-                break;
+                books.clear();
             }
-            Some(Ok(tungstenite::Message::Text(text))) => match decode_event(&text) {
-                Err(e) => log::error!("error decoding event: {:?}", e),
-                Ok(BitvavoEvent::Authenticated) => log::info!("successfully authenticated"),
-                Ok(BitvavoEvent::Subscribed) => log::info!("successfully subscribed"),
-                Ok(BitvavoEvent::Book(book)) => local_book.ingest_book(book),
-                Ok(BitvavoEvent::Candle(_e)) => {}
-                Ok(BitvavoEvent::Trade(_e)) => {}
-                Ok(BitvavoEvent::Markets(_markets)) => {}
-                Ok(BitvavoEvent::TickerBook(_e)) => {}
-                Ok(BitvavoEvent::Ticker24h(_e)) => {}
-                Ok(BitvavoEvent::Balances(b)) => balances = b,
-                Ok(BitvavoEvent::Ticker(ticker)) => local_book.ingest_ticker(ticker),
-            },
-            Some(Ok(tungstenite::Message::Ping(m))) => {
-                bitvavo.pong(m).await.expect("failed to pong")
+            ConnectionEvent::Reconnecting { attempt, delay } => {
+                log::info!("reconnecting in {:?} (attempt {})", delay, attempt)
             }
-            // etc
-            _ => {}
+            ConnectionEvent::Event(Err(e)) => log::error!("error decoding event: {:?}", e),
+            ConnectionEvent::Event(Ok(event)) => match event {
+                BitvavoEvent::Authenticated => log::info!("successfully authenticated"),
+                BitvavoEvent::Subscribed => log::info!("successfully subscribed"),
+                BitvavoEvent::Book(book) => {
+                    let market = book.market.clone();
+                    resync(&connection, &market, books.ingest_book(book)).await;
+                }
+                BitvavoEvent::BookSnapshot(book) => {
+                    let market = book.market.clone();
+                    resync(&connection, &market, books.ingest_snapshot(book)).await;
+                }
+                BitvavoEvent::Ticker(ticker) => books.ingest_ticker(ticker),
+                BitvavoEvent::Trade(trade) => {
+                    let market = trade.market.clone();
+                    tape.ingest(trade);
+                    if let Some(stats) = tape.stats(&market) {
+                        log::info!(
+                            "{} last minute: {} trades, vwap {}, imbalance {}, largest {}",
+                            market,
+                            stats.trades,
+                            stats.vwap.round_dp(2),
+                            stats.imbalance().round_dp(2),
+                            stats.largest.amount,
+                        );
+                    }
+                }
+                // etc
+                _ => {}
+            },
+        }
+        for (market, top) in books.top_of_books() {
+            log::info!(
+                "{} top: {:?} : {:?}, spread: {}%",
+                market,
+                top.bid,
+                top.ask,
+                (top.spread * Decimal::from(100)).round_dp(4),
+            );
         }
-        log::info!(
-            "local book top: {:?} : {:?}, spread: {}%",
-            local_book.top_bid_or_default(),
-            local_book.top_ask_or_default(),
-            local_book.real_spread_or_default().format_as_percentage(),
-        );
     }
 }
@@ -0,0 +1,202 @@
+use crate::engine::Rejection;
+use bitvavo_tungstenite::error_code::BitvavoErrorCode;
+use bitvavo_tungstenite::sig::create_signature;
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::HashMap;
+
+// the exchange's limits on the window, in milliseconds
+const DEFAULT_WINDOW: u64 = 10_000;
+const MAX_WINDOW: u64 = 60_000;
+// how far ahead of the exchange's clock a request may be
+const MAX_AHEAD: u64 = 1_000;
+
+// an authenticate action; timestamp and window are taken as numbers or strings,
+// whatever the client sent is checked here rather than by serde
+#[derive(Debug, Deserialize)]
+pub struct AuthenticateRequest {
+    key: Option<String>,
+    signature: Option<String>,
+    timestamp: Option<Value>,
+    window: Option<Value>,
+}
+
+struct User {
+    guid: &'static str,
+    secret: &'static str,
+}
+
+pub struct UserStorage {
+    // by API key
+    users: HashMap<&'static str, User>,
+}
+
+impl UserStorage {
+    pub fn new() -> Self {
+        Self {
+            users: HashMap::from([(
+                "xxx_yyyy",
+                User {
+                    guid: "00000000-0000-0000-0000-000000000001",
+                    secret: "zzz_secret",
+                },
+            )]),
+        }
+    }
+
+    pub fn guids(&self) -> impl Iterator<Item = &'static str> {
+        self.users.values().map(|user| user.guid)
+    }
+
+    // the guid of the user, if the request is signed with their secret within
+    // its window of `now`; otherwise the error the exchange would return
+    pub fn authenticate(
+        &self,
+        request: &AuthenticateRequest,
+        now: u64,
+    ) -> Result<&'static str, Rejection> {
+        let user = request
+            .key
+            .as_deref()
+            .and_then(|key| self.users.get(key))
+            .ok_or_else(|| {
+                rejection(BitvavoErrorCode::NoActiveApiKey, "No active API key found.")
+            })?;
+        let timestamp =
+            request.timestamp.as_ref().and_then(number).ok_or_else(|| {
+                rejection(BitvavoErrorCode::InvalidTimestamp, "Invalid timestamp.")
+            })?;
+        let window = match &request.window {
+            None => DEFAULT_WINDOW,
+            Some(window) => number(window)
+                .filter(|window| (1..=MAX_WINDOW).contains(window))
+                .ok_or_else(|| {
+                    rejection(
+                        BitvavoErrorCode::InvalidWindow,
+                        "Window must be between 1 and 60000 ms.",
+                    )
+                })?,
+        };
+        if timestamp.saturating_add(window) < now || timestamp > now + MAX_AHEAD {
+            return Err(rejection(
+                BitvavoErrorCode::OutsideAcceptanceWindow,
+                "Request was not received within acceptance window.",
+            ));
+        }
+
+        let signature = request.signature.as_deref().unwrap_or_default();
+        if signature.len() != 64 {
+            return Err(rejection(
+                BitvavoErrorCode::InvalidSignatureLength,
+                "The signature length is invalid (HMAC-SHA256 should return a 64 length hexadecimal string).",
+            ));
+        }
+        let expected = create_signature(
+            &timestamp.to_string(),
+            "GET",
+            "/websocket",
+            HashMap::new(),
+            user.secret,
+        );
+        if !signature.eq_ignore_ascii_case(&expected) {
+            return Err(rejection(
+                BitvavoErrorCode::InvalidSignature,
+                "The signature is invalid.",
+            ));
+        }
+        Ok(user.guid)
+    }
+}
+
+fn number(value: &Value) -> Option<u64> {
+    match value {
+        Value::String(s) => s.parse().ok(),
+        value => value.as_u64(),
+    }
+}
+
+fn rejection(error_code: BitvavoErrorCode, error: &str) -> Rejection {
+    Rejection {
+        error_code,
+        error: error.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitvavo_tungstenite::event::AuthRequest;
+
+    fn request(key: &str, secret: &str) -> Value {
+        serde_json::to_value(AuthRequest::make(key, secret)).unwrap()
+    }
+
+    fn authenticate(request: Value, now: u64) -> Result<&'static str, BitvavoErrorCode> {
+        let request = serde_json::from_value(request).unwrap();
+        UserStorage::new()
+            .authenticate(&request, now)
+            .map_err(|rejection| rejection.error_code)
+    }
+
+    #[test]
+    fn verify_signature_and_window() {
+        let signed = request("xxx_yyyy", "zzz_secret");
+        let timestamp = signed["timestamp"].as_u64().unwrap();
+        assert_eq!(
+            authenticate(signed.clone(), timestamp + 1_000),
+            Ok("00000000-0000-0000-0000-000000000001")
+        );
+        // the client sends a 1.5 second window
+        assert_eq!(
+            authenticate(signed.clone(), timestamp + 2_000),
+            Err(BitvavoErrorCode::OutsideAcceptanceWindow)
+        );
+        assert_eq!(
+            authenticate(signed, timestamp - 5_000),
+            Err(BitvavoErrorCode::OutsideAcceptanceWindow)
+        );
+
+        let wrong_secret = request("xxx_yyyy", "secret");
+        let timestamp = wrong_secret["timestamp"].as_u64().unwrap();
+        assert_eq!(
+            authenticate(wrong_secret, timestamp),
+            Err(BitvavoErrorCode::InvalidSignature)
+        );
+    }
+
+    #[test]
+    fn reject_malformed_requests() {
+        let signed = request("xxx_yyyy", "zzz_secret");
+        let timestamp = signed["timestamp"].as_u64().unwrap();
+        let with = |field: &str, value: Value| {
+            let mut request = signed.clone();
+            request[field] = value;
+            authenticate(request, timestamp)
+        };
+        assert_eq!(
+            with("key", "unknown".into()),
+            Err(BitvavoErrorCode::NoActiveApiKey)
+        );
+        assert_eq!(
+            with("timestamp", "soon".into()),
+            Err(BitvavoErrorCode::InvalidTimestamp)
+        );
+        // far in the future rather than overflowing
+        assert_eq!(
+            with("timestamp", "18446744073709551615".into()),
+            Err(BitvavoErrorCode::OutsideAcceptanceWindow)
+        );
+        assert_eq!(
+            with("window", "70000".into()),
+            Err(BitvavoErrorCode::InvalidWindow)
+        );
+        assert_eq!(
+            with("signature", "abc".into()),
+            Err(BitvavoErrorCode::InvalidSignatureLength)
+        );
+        // without a window the exchange's default of 10 seconds applies
+        let mut request = signed.clone();
+        request.as_object_mut().unwrap().remove("window");
+        assert!(authenticate(request, timestamp + 9_000).is_ok());
+    }
+}
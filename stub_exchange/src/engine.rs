@@ -0,0 +1,780 @@
+use bitvavo_tungstenite::decimal::{Decimal, Rounding};
+use bitvavo_tungstenite::error_code::BitvavoErrorCode;
+use bitvavo_tungstenite::event::{Balance, Fill, Order};
+use bitvavo_tungstenite::market::Pair;
+use bitvavo_tungstenite::order::{OrderStatus, OrderType, TimeInForce};
+use bitvavo_tungstenite::rug_float_serde::FloatWrapper;
+use bitvavo_tungstenite::side::Side;
+use bitvavo_tungstenite::trade::Trade;
+use serde::Deserialize;
+use serde_json::json;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+// amounts derived from a quote amount are truncated to this many decimals
+const AMOUNT_DECIMALS: u32 = 8;
+
+// (price, amount)
+pub type Level = (Decimal, Decimal);
+
+// a privateCreateOrder request, as far as the engine supports it
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlaceOrder {
+    pub market: String,
+    pub side: Side,
+    pub order_type: OrderType,
+    pub amount: Option<Decimal>,
+    pub amount_quote: Option<Decimal>,
+    pub price: Option<Decimal>,
+    pub time_in_force: Option<TimeInForce>,
+    #[serde(default)]
+    pub post_only: bool,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Rejection {
+    pub error_code: BitvavoErrorCode,
+    pub error: String,
+}
+
+pub fn reject<T>(error_code: BitvavoErrorCode, error: &str) -> Result<T, Rejection> {
+    Err(Rejection {
+        error_code,
+        error: error.to_string(),
+    })
+}
+
+// an account channel event for one user
+#[derive(Debug)]
+pub struct Notification {
+    pub user: String,
+    pub market: String,
+    pub event: serde_json::Value,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct Holding {
+    available: Decimal,
+    in_order: Decimal,
+}
+
+#[derive(Debug)]
+struct OrderState {
+    id: String,
+    user: String,
+    pair: Pair,
+    side: Side,
+    order_type: OrderType,
+    created: u64,
+    updated: u64,
+    status: OrderStatus,
+    amount: Option<Decimal>,
+    amount_quote: Option<Decimal>,
+    price: Option<Decimal>,
+    time_in_force: Option<TimeInForce>,
+    post_only: bool,
+    filled_amount: Decimal,
+    filled_amount_quote: Decimal,
+    // set aside for what is left: quote when buying, base when selling
+    on_hold: Decimal,
+    fills: Vec<Fill>,
+}
+
+impl OrderState {
+    fn remaining(&self) -> Option<Decimal> {
+        self.amount.map(|amount| amount - self.filled_amount)
+    }
+
+    fn hold_currency(&self) -> &str {
+        match self.side {
+            Side::Buy => &self.pair.quote,
+            Side::Sell => &self.pair.base,
+        }
+    }
+
+    // whether a resting order at `price` is good enough
+    fn accepts(&self, price: Decimal) -> bool {
+        match (self.price, self.side) {
+            (None, _) => true,
+            (Some(limit), Side::Buy) => price <= limit,
+            (Some(limit), Side::Sell) => price >= limit,
+        }
+    }
+
+    fn to_order(&self) -> Order {
+        let is_limit = self.order_type == OrderType::Limit;
+        Order {
+            order_id: self.id.clone(),
+            market: self.pair.to_string(),
+            created: self.created,
+            updated: self.updated,
+            status: self.status,
+            side: self.side,
+            order_type: self.order_type,
+            amount: self.amount.map(FloatWrapper::from),
+            amount_remaining: self.remaining().map(FloatWrapper::from),
+            price: self.price.map(FloatWrapper::from),
+            amount_quote: self.amount_quote.map(FloatWrapper::from),
+            amount_quote_remaining: self
+                .amount_quote
+                .map(|quote| FloatWrapper::from(quote - self.filled_amount_quote)),
+            on_hold: Some(FloatWrapper::from(self.on_hold)),
+            on_hold_currency: Some(self.hold_currency().to_string()),
+            filled_amount: Some(FloatWrapper::from(self.filled_amount)),
+            filled_amount_quote: Some(FloatWrapper::from(self.filled_amount_quote)),
+            fee_paid: Some(FloatWrapper::from(Decimal::ZERO)),
+            fee_currency: Some(self.pair.quote.clone()),
+            fills: self.fills.clone(),
+            self_trade_prevention: None,
+            visible: is_limit,
+            disable_market_protection: false,
+            time_in_force: self.time_in_force,
+            post_only: self.post_only,
+            trigger_amount: None,
+            trigger_price: None,
+            trigger_type: None,
+            trigger_reference: None,
+        }
+    }
+
+    fn order_event(&self) -> Notification {
+        let mut event = serde_json::to_value(self.to_order()).unwrap();
+        event["event"] = json!("order");
+        Notification {
+            user: self.user.clone(),
+            market: self.pair.to_string(),
+            event,
+        }
+    }
+
+    fn fill_event(&self, fill: &Fill) -> Notification {
+        Notification {
+            user: self.user.clone(),
+            market: self.pair.to_string(),
+            event: json!({
+                "event": "fill",
+                "market": self.pair.to_string(),
+                "orderId": self.id,
+                "fillId": fill.id,
+                "timestamp": fill.timestamp,
+                "amount": fill.amount,
+                "side": self.side,
+                "price": fill.price,
+                "taker": fill.taker,
+                "fee": fill.fee,
+                "feeCurrency": fill.fee_currency,
+                "settled": fill.settled,
+            }),
+        }
+    }
+}
+
+// order ids per price level, oldest first
+#[derive(Debug, Default)]
+struct Book {
+    bids: BTreeMap<Decimal, VecDeque<String>>,
+    asks: BTreeMap<Decimal, VecDeque<String>>,
+}
+
+impl Book {
+    // the best resting level an order on `side` trades against
+    fn best_against(&mut self, side: Side) -> Option<(Decimal, &mut VecDeque<String>)> {
+        let level = match side {
+            Side::Buy => self.asks.iter_mut().next(),
+            Side::Sell => self.bids.iter_mut().next_back(),
+        };
+        level.map(|(price, ids)| (*price, ids))
+    }
+
+    fn levels_against(
+        &self,
+        side: Side,
+    ) -> Box<dyn Iterator<Item = (&Decimal, &VecDeque<String>)> + '_> {
+        match side {
+            Side::Buy => Box::new(self.asks.iter()),
+            Side::Sell => Box::new(self.bids.iter().rev()),
+        }
+    }
+
+    fn rest(&mut self, side: Side, price: Decimal, id: String) {
+        let levels = match side {
+            Side::Buy => &mut self.bids,
+            Side::Sell => &mut self.asks,
+        };
+        levels.entry(price).or_default().push_back(id);
+    }
+
+    fn remove(&mut self, side: Side, price: Decimal, id: &str) {
+        let levels = match side {
+            Side::Buy => &mut self.bids,
+            Side::Sell => &mut self.asks,
+        };
+        if let Some(ids) = levels.get_mut(&price) {
+            ids.retain(|resting| resting != id);
+            if ids.is_empty() {
+                levels.remove(&price);
+            }
+        }
+    }
+}
+
+// price-time priority books for any market, and the balances of the users
+// trading on them; there are no fees and no self-trade prevention
+#[derive(Debug, Default)]
+pub struct Engine {
+    books: HashMap<String, Book>,
+    orders: HashMap<String, OrderState>,
+    balances: HashMap<String, BTreeMap<String, Holding>>,
+    // public trades not yet taken by the market data
+    trades: Vec<Trade>,
+    next_id: u64,
+}
+
+impl Engine {
+    pub fn markets(&self) -> impl Iterator<Item = &String> {
+        self.books.keys()
+    }
+
+    // the bids and the asks, best first
+    pub fn levels(&self, market: &str) -> (Vec<Level>, Vec<Level>) {
+        let Some(book) = self.books.get(market) else {
+            return (Vec::new(), Vec::new());
+        };
+        let amount = |ids: &VecDeque<String>| {
+            ids.iter()
+                .filter_map(|id| self.orders[id].remaining())
+                .sum::<Decimal>()
+        };
+        (
+            book.bids
+                .iter()
+                .rev()
+                .map(|(price, ids)| (*price, amount(ids)))
+                .collect(),
+            book.asks
+                .iter()
+                .map(|(price, ids)| (*price, amount(ids)))
+                .collect(),
+        )
+    }
+
+    // the trades since the last call, oldest first
+    pub fn take_trades(&mut self) -> Vec<Trade> {
+        std::mem::take(&mut self.trades)
+    }
+
+    pub fn deposit(&mut self, user: &str, symbol: &str, amount: Decimal) {
+        holding(&mut self.balances, user, symbol).available += amount;
+    }
+
+    pub fn balances(&self, user: &str) -> Vec<Balance> {
+        self.balances
+            .get(user)
+            .into_iter()
+            .flatten()
+            .map(|(symbol, holding)| Balance {
+                symbol: symbol.clone(),
+                available: holding.available,
+                in_order: holding.in_order,
+            })
+            .collect()
+    }
+
+    // matches the order against the book and rests what is left of a limit
+    // order; returns it as it stands afterwards, with the events for everyone
+    // it traded with
+    pub fn place(
+        &mut self,
+        user: &str,
+        request: PlaceOrder,
+        now: u64,
+    ) -> Result<(Order, Vec<Notification>), Rejection> {
+        let Ok(pair) = request.market.parse::<Pair>() else {
+            return reject(BitvavoErrorCode::InvalidParameter, "market is invalid.");
+        };
+        let positive = |value: Option<Decimal>| value.is_none_or(|v| v > Decimal::ZERO);
+        if !positive(request.amount) || !positive(request.amount_quote) || !positive(request.price)
+        {
+            return reject(
+                BitvavoErrorCode::InvalidParameter,
+                "amounts and prices should be positive.",
+            );
+        }
+        match (
+            request.order_type,
+            request.amount,
+            request.amount_quote,
+            request.price,
+        ) {
+            (OrderType::Limit, Some(_), None, Some(_)) => {}
+            (OrderType::Market, Some(_), None, None) | (OrderType::Market, None, Some(_), None) => {
+            }
+            (OrderType::Limit | OrderType::Market, ..) => {
+                return reject(
+                    BitvavoErrorCode::MissingOrIncompatibleParameters,
+                    "amount, amountQuote or price are missing or incompatible.",
+                );
+            }
+            _ => {
+                return reject(
+                    BitvavoErrorCode::InvalidParameter,
+                    "only limit and market orders are supported.",
+                );
+            }
+        }
+
+        self.next_id += 1;
+        let mut taker = OrderState {
+            id: uuid(self.next_id),
+            user: user.to_string(),
+            pair,
+            side: request.side,
+            order_type: request.order_type,
+            created: now,
+            updated: now,
+            status: OrderStatus::New,
+            amount: request.amount,
+            amount_quote: request.amount_quote,
+            price: request.price,
+            time_in_force: match request.order_type {
+                OrderType::Limit => Some(
+                    request
+                        .time_in_force
+                        .unwrap_or(TimeInForce::GoodTillCancelled),
+                ),
+                _ => None,
+            },
+            post_only: request.post_only,
+            filled_amount: Decimal::ZERO,
+            filled_amount_quote: Decimal::ZERO,
+            on_hold: Decimal::ZERO,
+            fills: Vec::new(),
+        };
+
+        // what the order can spend at most; a market order without a known
+        // cost may use everything available
+        let available = holding(&mut self.balances, user, taker.hold_currency()).available;
+        let hold = match (taker.side, taker.amount, taker.amount_quote, taker.price) {
+            (Side::Buy, Some(amount), _, Some(price)) => amount * price,
+            (Side::Buy, _, Some(amount_quote), _) => amount_quote,
+            (Side::Sell, Some(amount), _, _) => amount,
+            _ => available,
+        };
+        if hold.is_zero() || hold > available {
+            return reject(
+                BitvavoErrorCode::InsufficientBalance,
+                "Insufficient balance to perform this action.",
+            );
+        }
+        let holding = holding(&mut self.balances, user, taker.hold_currency());
+        holding.available -= hold;
+        holding.in_order += hold;
+        taker.on_hold = hold;
+
+        let book = self.books.entry(taker.pair.to_string()).or_default();
+        let crosses = book
+            .levels_against(taker.side)
+            .next()
+            .is_some_and(|(price, _)| taker.accepts(*price));
+        let fillable = || {
+            book.levels_against(taker.side)
+                .take_while(|(price, _)| taker.accepts(**price))
+                .flat_map(|(_, ids)| ids)
+                .filter_map(|id| self.orders.get(id)?.remaining())
+                .sum::<Decimal>()
+        };
+        let mut notifications = Vec::new();
+        if taker.post_only && crosses {
+            taker.status = OrderStatus::CanceledPostOnly;
+        } else if taker.time_in_force == Some(TimeInForce::FillOrKill)
+            && taker
+                .remaining()
+                .is_some_and(|remaining| fillable() < remaining)
+        {
+            taker.status = OrderStatus::CanceledFok;
+        } else {
+            self.match_order(&mut taker, now, &mut notifications);
+            taker.status = match (taker.order_type, taker.time_in_force, taker.remaining()) {
+                (_, _, Some(remaining)) if remaining.is_zero() => OrderStatus::Filled,
+                (OrderType::Limit, Some(TimeInForce::ImmediateOrCancel), _) => {
+                    OrderStatus::CanceledIoc
+                }
+                (OrderType::Limit, ..) if taker.fills.is_empty() => OrderStatus::New,
+                (OrderType::Limit, ..) => OrderStatus::PartiallyFilled,
+                // whatever liquidity there was has been taken
+                _ if taker.fills.is_empty() => OrderStatus::Canceled,
+                _ => OrderStatus::Filled,
+            };
+        }
+
+        if taker.status.is_open() {
+            let book = self.books.entry(taker.pair.to_string()).or_default();
+            book.rest(taker.side, taker.price.unwrap(), taker.id.clone());
+        } else {
+            release(&mut self.balances, &mut taker);
+        }
+        notifications.push(taker.order_event());
+        let order = taker.to_order();
+        self.orders.insert(taker.id.clone(), taker);
+        Ok((order, notifications))
+    }
+
+    pub fn cancel(
+        &mut self,
+        user: &str,
+        order_id: &str,
+        now: u64,
+    ) -> Result<(String, Vec<Notification>), Rejection> {
+        let Some(order) = self
+            .orders
+            .get_mut(order_id)
+            .filter(|order| order.user == user && order.status.is_open())
+        else {
+            return reject(BitvavoErrorCode::OrderNotFound, "No order found.");
+        };
+        if let Some(book) = self.books.get_mut(&order.pair.to_string()) {
+            book.remove(order.side, order.price.unwrap(), &order.id);
+        }
+        order.status = OrderStatus::Canceled;
+        order.updated = now;
+        release(&mut self.balances, order);
+        Ok((order.id.clone(), vec![order.order_event()]))
+    }
+
+    // the open orders of the user, in one market or all of them
+    pub fn cancel_all(
+        &mut self,
+        user: &str,
+        market: Option<&str>,
+        now: u64,
+    ) -> (Vec<String>, Vec<Notification>) {
+        let mut ids = self
+            .orders
+            .values()
+            .filter(|order| order.user == user && order.status.is_open())
+            .filter(|order| market.is_none_or(|market| order.pair.to_string() == market))
+            .map(|order| (order.created, order.id.clone()))
+            .collect::<Vec<_>>();
+        ids.sort();
+        let mut cancelled = Vec::new();
+        let mut notifications = Vec::new();
+        for (_, id) in ids {
+            if let Ok((id, events)) = self.cancel(user, &id, now) {
+                cancelled.push(id);
+                notifications.extend(events);
+            }
+        }
+        (cancelled, notifications)
+    }
+
+    fn match_order(
+        &mut self,
+        taker: &mut OrderState,
+        now: u64,
+        notifications: &mut Vec<Notification>,
+    ) {
+        let book = self.books.entry(taker.pair.to_string()).or_default();
+        while let Some((price, ids)) = book.best_against(taker.side)
+            && taker.accepts(price)
+        {
+            let maker_id = ids.front().unwrap().clone();
+            let maker = self.orders.get_mut(&maker_id).unwrap();
+
+            let mut amount = maker.remaining().unwrap();
+            if let Some(remaining) = taker.remaining() {
+                amount = amount.min(remaining);
+            }
+            // a taker without an amount is bounded by what it holds
+            let affordable = match taker.side {
+                Side::Buy if taker.price.is_none() => {
+                    (taker.on_hold / price).round_dp_with(AMOUNT_DECIMALS, Rounding::Down)
+                }
+                Side::Buy => amount,
+                Side::Sell => taker.on_hold,
+            };
+            amount = amount.min(affordable);
+            if let Some(amount_quote) = taker.amount_quote
+                && taker.side == Side::Sell
+            {
+                let left = amount_quote - taker.filled_amount_quote;
+                amount = amount.min((left / price).round_dp_with(AMOUNT_DECIMALS, Rounding::Down));
+            }
+            if amount.is_zero() {
+                break;
+            }
+
+            self.next_id += 1;
+            let fill_id = uuid(self.next_id);
+            let maker_fill = execute(
+                &mut self.balances,
+                maker,
+                amount,
+                price,
+                false,
+                &fill_id,
+                now,
+            );
+            let taker_fill = execute(
+                &mut self.balances,
+                taker,
+                amount,
+                price,
+                true,
+                &fill_id,
+                now,
+            );
+            notifications.push(maker.fill_event(&maker_fill));
+            notifications.push(taker.fill_event(&taker_fill));
+            self.trades.push(Trade {
+                market: taker.pair.to_string(),
+                timestamp: now,
+                id: fill_id,
+                amount,
+                price,
+                side: taker.side,
+            });
+
+            if maker.remaining().unwrap().is_zero() {
+                maker.status = OrderStatus::Filled;
+                release(&mut self.balances, maker);
+                ids.pop_front();
+                if ids.is_empty() {
+                    book.remove(maker.side, price, &maker_id);
+                }
+            } else {
+                maker.status = OrderStatus::PartiallyFilled;
+            }
+            notifications.push(maker.order_event());
+        }
+    }
+}
+
+fn holding<'a>(
+    balances: &'a mut HashMap<String, BTreeMap<String, Holding>>,
+    user: &str,
+    symbol: &str,
+) -> &'a mut Holding {
+    balances
+        .entry(user.to_string())
+        .or_default()
+        .entry(symbol.to_string())
+        .or_default()
+}
+
+// trades `amount` at `price` for one side, paying out of what the order holds
+fn execute(
+    balances: &mut HashMap<String, BTreeMap<String, Holding>>,
+    order: &mut OrderState,
+    amount: Decimal,
+    price: Decimal,
+    taker: bool,
+    fill_id: &str,
+    now: u64,
+) -> Fill {
+    let cost = amount * price;
+    let (spent, received, released) = match order.side {
+        // a limit buy set aside its own price, the difference goes back
+        Side::Buy => (
+            cost,
+            amount,
+            order.price.map_or(cost, |limit| amount * limit),
+        ),
+        Side::Sell => (amount, cost, amount),
+    };
+    let (pay, receive) = match order.side {
+        Side::Buy => (order.pair.quote.clone(), order.pair.base.clone()),
+        Side::Sell => (order.pair.base.clone(), order.pair.quote.clone()),
+    };
+    let paid = holding(balances, &order.user, &pay);
+    paid.in_order -= released;
+    paid.available += released - spent;
+    holding(balances, &order.user, &receive).available += received;
+
+    order.on_hold -= released;
+    order.filled_amount += amount;
+    order.filled_amount_quote += cost;
+    order.updated = now;
+    let fill = Fill {
+        id: fill_id.to_string(),
+        order_id: None,
+        market: None,
+        side: None,
+        timestamp: now,
+        amount: FloatWrapper::from(amount),
+        price: FloatWrapper::from(price),
+        taker,
+        fee: Some(FloatWrapper::from(Decimal::ZERO)),
+        fee_currency: Some(order.pair.quote.clone()),
+        settled: true,
+    };
+    order.fills.push(fill.clone());
+    fill
+}
+
+// gives back what a closed order still held
+fn release(balances: &mut HashMap<String, BTreeMap<String, Holding>>, order: &mut OrderState) {
+    let holding = holding(balances, &order.user, order.hold_currency());
+    holding.in_order -= order.on_hold;
+    holding.available += order.on_hold;
+    order.on_hold = Decimal::ZERO;
+}
+
+fn uuid(id: u64) -> String {
+    format!("00000000-0000-4000-8000-{:012x}", id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn d(s: &str) -> Decimal {
+        s.parse().unwrap()
+    }
+
+    fn limit(side: Side, amount: &str, price: &str) -> PlaceOrder {
+        PlaceOrder {
+            market: "BTC-EUR".to_string(),
+            side,
+            order_type: OrderType::Limit,
+            amount: Some(d(amount)),
+            amount_quote: None,
+            price: Some(d(price)),
+            time_in_force: None,
+            post_only: false,
+        }
+    }
+
+    fn balance(engine: &Engine, user: &str, symbol: &str) -> (Decimal, Decimal) {
+        engine
+            .balances(user)
+            .into_iter()
+            .find(|b| b.symbol == symbol)
+            .map_or((Decimal::ZERO, Decimal::ZERO), |b| {
+                (b.available, b.in_order)
+            })
+    }
+
+    fn engine() -> Engine {
+        let mut engine = Engine::default();
+        for user in ["maker", "taker"] {
+            engine.deposit(user, "BTC", d("10"));
+            engine.deposit(user, "EUR", d("10000"));
+        }
+        engine
+    }
+
+    #[test]
+    fn match_limit_orders_at_the_resting_price() {
+        let mut engine = engine();
+        let (resting, _) = engine
+            .place("maker", limit(Side::Sell, "1", "100"), 1)
+            .unwrap();
+        assert_eq!(resting.status, OrderStatus::New);
+        assert_eq!(balance(&engine, "maker", "BTC"), (d("9"), d("1")));
+
+        let (order, events) = engine
+            .place("taker", limit(Side::Buy, "0.4", "101"), 2)
+            .unwrap();
+        assert_eq!(order.status, OrderStatus::Filled);
+        assert_eq!(order.fills[0].price.str_repr, "100");
+        // paid 40, the extra 0.4 set aside at 101 went back
+        assert_eq!(balance(&engine, "taker", "EUR"), (d("9960"), d("0")));
+        assert_eq!(balance(&engine, "taker", "BTC"), (d("10.4"), d("0")));
+        assert_eq!(balance(&engine, "maker", "EUR"), (d("10040"), d("0")));
+        assert_eq!(balance(&engine, "maker", "BTC"), (d("9"), d("0.6")));
+
+        let kinds: Vec<_> = events
+            .iter()
+            .map(|n| (n.user.as_str(), n.event["event"].as_str().unwrap()))
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                ("maker", "fill"),
+                ("taker", "fill"),
+                ("maker", "order"),
+                ("taker", "order")
+            ]
+        );
+        assert_eq!(events[2].event["status"], "partiallyFilled");
+    }
+
+    #[test]
+    fn price_time_priority() {
+        let mut engine = engine();
+        let (first, _) = engine
+            .place("maker", limit(Side::Buy, "1", "99"), 1)
+            .unwrap();
+        let (second, _) = engine
+            .place("maker", limit(Side::Buy, "1", "99"), 2)
+            .unwrap();
+        let (better, _) = engine
+            .place("maker", limit(Side::Buy, "1", "99.5"), 3)
+            .unwrap();
+
+        let (_, events) = engine
+            .place("taker", limit(Side::Sell, "2.5", "99"), 4)
+            .unwrap();
+        let filled: Vec<_> = events
+            .iter()
+            .filter(|n| n.user == "maker" && n.event["event"] == "fill")
+            .map(|n| n.event["orderId"].as_str().unwrap().to_string())
+            .collect();
+        assert_eq!(
+            filled,
+            vec![better.order_id, first.order_id, second.order_id]
+        );
+    }
+
+    #[test]
+    fn market_orders_and_cancels() {
+        let mut engine = engine();
+        engine
+            .place("maker", limit(Side::Sell, "1", "100"), 1)
+            .unwrap();
+        let (resting, _) = engine
+            .place("maker", limit(Side::Sell, "1", "110"), 2)
+            .unwrap();
+
+        let market_buy = PlaceOrder {
+            order_type: OrderType::Market,
+            amount: None,
+            amount_quote: Some(d("155")),
+            price: None,
+            ..limit(Side::Buy, "1", "1")
+        };
+        let (order, _) = engine.place("taker", market_buy, 3).unwrap();
+        assert_eq!(order.status, OrderStatus::Filled);
+        // 1 @ 100 and 0.5 @ 110
+        assert_eq!(order.filled_amount.unwrap().str_repr, "1.5");
+        assert_eq!(balance(&engine, "taker", "EUR"), (d("9845"), d("0")));
+
+        let (cancelled, _) = engine.cancel("maker", &resting.order_id, 4).unwrap();
+        assert_eq!(cancelled, resting.order_id);
+        assert_eq!(balance(&engine, "maker", "BTC"), (d("8.5"), d("0")));
+        assert_eq!(
+            engine
+                .cancel("maker", &resting.order_id, 5)
+                .unwrap_err()
+                .error_code,
+            BitvavoErrorCode::OrderNotFound
+        );
+
+        // nothing left to buy from
+        let ioc = PlaceOrder {
+            time_in_force: Some(TimeInForce::ImmediateOrCancel),
+            ..limit(Side::Buy, "1", "120")
+        };
+        let (order, _) = engine.place("taker", ioc, 6).unwrap();
+        assert_eq!(order.status, OrderStatus::CanceledIoc);
+        assert_eq!(balance(&engine, "taker", "EUR"), (d("9845"), d("0")));
+
+        assert_eq!(
+            engine
+                .place("taker", limit(Side::Buy, "1000", "100"), 7)
+                .unwrap_err()
+                .error_code,
+            BitvavoErrorCode::InsufficientBalance
+        );
+    }
+}
@@ -0,0 +1,106 @@
+use crate::engine::Notification;
+use crate::market_data::Publication;
+use serde_json::json;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+
+#[derive(Debug, Default)]
+struct Connection {
+    user: Option<String>,
+    // (channel, market)
+    subscriptions: HashSet<(String, String)>,
+    outbox: VecDeque<serde_json::Value>,
+}
+
+// the open connections and what they subscribed to; events are queued per
+// connection and sent by the thread owning its socket
+#[derive(Debug, Default)]
+pub struct Hub {
+    connections: HashMap<u64, Connection>,
+    next_id: u64,
+}
+
+impl Hub {
+    pub fn connect(&mut self) -> u64 {
+        self.next_id += 1;
+        self.connections.insert(self.next_id, Connection::default());
+        self.next_id
+    }
+
+    pub fn disconnect(&mut self, id: u64) {
+        self.connections.remove(&id);
+    }
+
+    pub fn authenticate(&mut self, id: u64, user: &str) {
+        if let Some(connection) = self.connections.get_mut(&id) {
+            connection.user = Some(user.to_string());
+        }
+    }
+
+    pub fn subscribe(&mut self, id: u64, channel: &str, market: &str) {
+        if let Some(connection) = self.connections.get_mut(&id) {
+            connection
+                .subscriptions
+                .insert((channel.to_string(), market.to_string()));
+        }
+    }
+
+    pub fn unsubscribe(&mut self, id: u64, channel: &str, market: &str) {
+        if let Some(connection) = self.connections.get_mut(&id) {
+            connection
+                .subscriptions
+                .remove(&(channel.to_string(), market.to_string()));
+        }
+    }
+
+    // to every connection of the user subscribed to the account channel of the
+    // market
+    pub fn notify(&mut self, notification: Notification) {
+        let subscription = ("account".to_string(), notification.market);
+        for connection in self.connections.values_mut() {
+            if connection.user.as_ref() == Some(&notification.user)
+                && connection.subscriptions.contains(&subscription)
+            {
+                connection.outbox.push_back(notification.event.clone());
+            }
+        }
+    }
+
+    // to every connection subscribed to the channel of the market
+    pub fn publish(&mut self, publication: Publication) {
+        let subscription = (publication.channel, publication.market);
+        for connection in self.connections.values_mut() {
+            if connection.subscriptions.contains(&subscription) {
+                connection.outbox.push_back(publication.event.clone());
+            }
+        }
+    }
+
+    // the `subscriptions` of a subscribed event: markets per channel, per
+    // interval for candles
+    pub fn subscriptions(&self, id: u64) -> serde_json::Value {
+        let mut channels = BTreeMap::<&str, serde_json::Value>::new();
+        let Some(connection) = self.connections.get(&id) else {
+            return json!(channels);
+        };
+        for (channel, market) in &connection.subscriptions {
+            let markets = match channel.split_once('_') {
+                Some((name, interval)) => {
+                    &mut channels.entry(name).or_insert_with(|| json!({}))[interval]
+                }
+                None => channels.entry(channel).or_insert_with(|| json!([])),
+            };
+            match markets.as_array_mut() {
+                Some(markets) => markets.push(json!(market)),
+                None => *markets = json!([market]),
+            }
+        }
+        json!(channels)
+    }
+
+    pub fn drain(&mut self, id: u64) -> Vec<serde_json::Value> {
+        self.connections
+            .get_mut(&id)
+            .map(|connection| connection.outbox.drain(..).collect())
+            .unwrap_or_default()
+    }
+}
@@ -1,11 +1,26 @@
-use bitvavo_tungstenite::event::AuthRequest;
+mod auth;
+mod engine;
+mod hub;
+mod market_data;
+mod scenario;
+
+use crate::auth::{AuthenticateRequest, UserStorage};
+use crate::engine::{Engine, Notification, PlaceOrder, Rejection, reject};
+use crate::hub::Hub;
+use crate::market_data::{MarketData, channel_key};
+use crate::scenario::{Fault, Playback, Scenario};
+use bitvavo_tungstenite::error_code::BitvavoErrorCode;
+use bitvavo_tungstenite::trade::Trade;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
-use std::collections::{HashMap, LinkedList};
-use std::net::TcpListener;
-use std::sync::{Arc, RwLock};
-use std::thread::spawn;
-use tungstenite::accept;
+use std::collections::HashMap;
+use std::io::ErrorKind;
+use std::net::{TcpListener, TcpStream};
+use std::path::Path;
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread::{sleep, spawn};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tungstenite::{WebSocket, accept};
 
 #[derive(Debug, Deserialize)]
 struct SubscriptionRequest {
@@ -17,142 +32,464 @@ struct SubscriptionRequest {
 struct Subscription {
     markets: Vec<String>,
     name: String,
+    // candles only
+    #[serde(default)]
+    interval: Vec<String>,
 }
 
-struct UserStorage {
-    users: HashMap<&'static str, &'static str>,
-}
-
-impl UserStorage {
-    pub fn new() -> Self {
-        Self {
-            users: HashMap::from([("xxx_yyyy", "00000000-0000-0000-0000-000000000001")]),
+impl Subscription {
+    fn keys(&self) -> Vec<String> {
+        match self.interval.as_slice() {
+            [] => vec![channel_key(&self.name, None)],
+            intervals => intervals
+                .iter()
+                .map(|interval| channel_key(&self.name, Some(interval)))
+                .collect(),
         }
     }
+}
 
-    pub fn tok_to_guid(&self, token: String) -> Option<&'static str> {
-        self.users.get(token.as_str()).copied()
-    }
+#[derive(Debug, Deserialize)]
+struct MarketRequest {
+    market: String,
+}
+
+// privateCancelOrder and privateCancelOrders
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CancelRequest {
+    order_id: Option<String>,
+    market: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 struct Request {
     pub action: String,
+    #[serde(rename = "requestId", skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<u64>,
 }
 
-fn main() {
-    env_logger::init();
-    let server = TcpListener::bind("127.0.0.1:9001").unwrap();
-    let connections = Arc::new(RwLock::new(HashMap::new()));
-    let user_storage = Arc::new(RwLock::new(UserStorage::new()));
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as u64
+}
 
-    for stream in server.incoming() {
-        let conn = Arc::clone(&connections);
-        let us = Arc::clone(&user_storage);
+// the response to an action, or the error the exchange would send instead
+fn respond(
+    action: &str,
+    request_id: Option<u64>,
+    response: Result<serde_json::Value, Rejection>,
+) -> serde_json::Value {
+    match response {
+        Ok(response) => json!({
+            "action": action,
+            "requestId": request_id,
+            "response": response,
+        }),
+        Err(rejection) => json!({
+            "action": action,
+            "requestId": request_id,
+            "errorCode": rejection.error_code.code(),
+            "error": rejection.error,
+        }),
+    }
+}
 
-        log::info!("spawning a new connection-thread");
+fn authentication_required<T>() -> Result<T, Rejection> {
+    Err(Rejection {
+        error_code: BitvavoErrorCode::AuthenticationRequired,
+        error: "Authenticate yourself first.".to_string(),
+    })
+}
 
-        spawn(move || {
-            let mut authenticated = false;
-            let mut websocket = accept(stream.unwrap()).unwrap();
+// sends what the last engine operation changed to the public channels
+fn publish_market_data(engine: &Mutex<Engine>, market_data: &Mutex<MarketData>, hub: &Mutex<Hub>) {
+    let mut engine = engine.lock().unwrap();
+    let mut trades = HashMap::<String, Vec<Trade>>::new();
+    for trade in engine.take_trades() {
+        trades.entry(trade.market.clone()).or_default().push(trade);
+    }
+    let mut market_data = market_data.lock().unwrap();
+    let mut hub = hub.lock().unwrap();
+    let now = now();
+    for market in engine.markets() {
+        let (bids, asks) = engine.levels(market);
+        let trades = trades.remove(market).unwrap_or_default();
+        for publication in market_data.update(market, &bids, &asks, trades, now) {
+            hub.publish(publication);
+        }
+    }
+}
 
-            loop {
-                match websocket.read() {
-                    Ok(tungstenite::Message::Ping(bytes)) => {
-                        websocket.write(tungstenite::Message::Pong(bytes)).unwrap()
-                    }
+// what every connection works on
+#[derive(Clone)]
+struct Exchange {
+    hub: Arc<Mutex<Hub>>,
+    engine: Arc<Mutex<Engine>>,
+    market_data: Arc<Mutex<MarketData>>,
+    users: Arc<RwLock<UserStorage>>,
+    scenario: Arc<Scenario>,
+}
 
-                    Ok(tungstenite::Message::Text(bytes)) => {
-                        let request = serde_json::from_slice::<Request>(bytes.as_ref());
-                        if request.is_err() {
-                            break;
-                        }
-                        match request.unwrap().action.as_str() {
-                            "privateGetBalance" => {
-                                if !authenticated {
-                                    break;
-                                }
-                                let bytes = json!({
-                                    "action": "privateGetBalance",
-                                    "response": [{
-                                        "symbol": "BTC",
-                                        "available": "1.57593193",
-                                        "inOrder": "0.00",
-                                    }, {
-                                        "symbol": "EUR",
-                                        "available": "214232.00",
-                                        "inOrder": "0.00",
-                                    }]
-                                })
-                                .to_string();
-                                websocket
-                                    .write(tungstenite::Message::Text(bytes.into()))
-                                    .unwrap();
-                                continue;
-                            }
-                            "authenticate" => {
-                                if authenticated {
-                                    // ignore
-                                    continue;
-                                }
-                                let maybe_guid =
-                                    serde_json::from_slice::<AuthRequest>(bytes.as_ref())
-                                        .ok()
-                                        .and_then(|req| us.write().unwrap().tok_to_guid(req.key));
-                                match maybe_guid {
-                                    Some(guid) => {
-                                        conn.write()
-                                            .unwrap()
-                                            .insert(guid, LinkedList::<serde_json::Value>::new());
-                                        authenticated = true;
-
-                                        let authenticated =
-                                            json!({ "event": "authenticate" }).to_string();
-
-                                        websocket
-                                            .write(tungstenite::Message::Text(authenticated.into()))
-                                            .unwrap();
-                                        log::info!(
-                                            "authenticated user : {}, confirmation sent",
-                                            guid
-                                        );
-                                        continue;
-                                    }
-                                    None => {
-                                        log::info!("authentication failed");
-                                        break;
-                                    }
-                                }
-                            }
-                            "subscribe" => {
-                                let request_result =
-                                    serde_json::from_slice::<SubscriptionRequest>(bytes.as_ref());
-                                if request_result.is_err() {
-                                    log::error!(
-                                        "subscription failed: {}",
-                                        request_result.unwrap_err()
-                                    );
-                                    break;
-                                }
-                                let subscribed = json!({ "event": "subscribe" }).to_string();
-                                websocket
-                                    .write(tungstenite::Message::Text(subscribed.into()))
-                                    .unwrap();
-                            }
-                            action => {
-                                log::info!("unknown action: {}", action);
-                                break;
-                            }
+// how a request is answered
+enum Answer {
+    Response(serde_json::Value),
+    // authenticate and (un)subscribe are answered with an event
+    Event(serde_json::Value),
+    Nothing,
+}
+
+struct Connection {
+    exchange: Exchange,
+    websocket: WebSocket<TcpStream>,
+    playback: Playback,
+    // in the hub
+    id: u64,
+    user: Option<&'static str>,
+}
+
+impl Connection {
+    fn new(exchange: Exchange, websocket: WebSocket<TcpStream>) -> Self {
+        // reads give up now and then so that queued events get sent while the
+        // client is quiet
+        websocket
+            .get_ref()
+            .set_read_timeout(Some(Duration::from_millis(20)))
+            .unwrap();
+        let id = exchange.hub.lock().unwrap().connect();
+        let playback = Playback::new(&exchange.scenario, now());
+        Connection {
+            exchange,
+            websocket,
+            playback,
+            id,
+            user: None,
+        }
+    }
+
+    // sends a frame unless the scenario wants the connection dropped by now
+    fn send_text(&mut self, text: String) -> Result<(), Box<tungstenite::Error>> {
+        if self.playback.may_send() {
+            self.websocket
+                .send(tungstenite::Message::Text(text.into()))?;
+        }
+        Ok(())
+    }
+
+    // responses are held back for the scenario's delay
+    fn send(&mut self, message: serde_json::Value) -> Result<(), Box<tungstenite::Error>> {
+        if self.playback.delay() > 0 && message.get("action").is_some() {
+            sleep(Duration::from_millis(self.playback.delay()));
+        }
+        self.send_text(message.to_string())
+    }
+
+    // until either side drops the connection; a client gone while something is
+    // sent to it ends it like any other
+    fn serve(&mut self) -> Result<(), Box<tungstenite::Error>> {
+        loop {
+            for fault in self.playback.due(now()) {
+                log::info!("scenario: {:?}", fault);
+                match fault {
+                    Fault::Send { events } => {
+                        for event in events {
+                            self.send(event)?;
                         }
                     }
-                    somethings_else => {
-                        log::error!("somethings_else: {:?}", somethings_else);
-                        break;
+                    Fault::SkipNonce { market } => self
+                        .exchange
+                        .market_data
+                        .lock()
+                        .unwrap()
+                        .skip_nonce(&market),
+                    Fault::Malformed { frame } => self.send_text(frame)?,
+                    _ => {}
+                }
+            }
+            let events = self.exchange.hub.lock().unwrap().drain(self.id);
+            for event in events {
+                self.send(event)?;
+            }
+            if self.playback.should_drop() {
+                log::info!("dropping the connection as scripted");
+                return Ok(());
+            }
+
+            let bytes = match self.websocket.read() {
+                Err(tungstenite::Error::Io(e))
+                    if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) =>
+                {
+                    continue;
+                }
+                Ok(tungstenite::Message::Ping(bytes)) => {
+                    self.websocket.send(tungstenite::Message::Pong(bytes))?;
+                    continue;
+                }
+                Ok(tungstenite::Message::Text(bytes)) => bytes,
+                somethings_else => {
+                    log::error!("somethings_else: {:?}", somethings_else);
+                    return Ok(());
+                }
+            };
+            let request = match serde_json::from_slice::<Request>(bytes.as_ref()) {
+                Ok(request) => request,
+                Err(e) => {
+                    self.send(respond("", None, Err(invalid_parameters(e))))?;
+                    continue;
+                }
+            };
+            // lets clients simulate the exchange dropping them
+            if request.action == "dropConnection" {
+                log::info!("dropping the connection on request");
+                return Ok(());
+            }
+            let answer = match self.playback.take_failure(&request.action) {
+                Some(rejection) => Err(rejection),
+                None => self.handle(&request.action, bytes.as_ref()),
+            };
+            let message = match answer {
+                Ok(Answer::Response(response)) => {
+                    respond(&request.action, request.request_id, Ok(response))
+                }
+                Ok(Answer::Event(event)) => event,
+                Ok(Answer::Nothing) => continue,
+                Err(rejection) => respond(&request.action, request.request_id, Err(rejection)),
+            };
+            self.send(message)?;
+        }
+    }
+
+    fn handle(&mut self, action: &str, bytes: &[u8]) -> Result<Answer, Rejection> {
+        match action {
+            "getBook" => self.handle_get_book(bytes).map(Answer::Response),
+            "privateGetBalance" => self.handle_get_balance().map(Answer::Response),
+            "privateCreateOrder" => self.handle_create_order(bytes).map(Answer::Response),
+            "privateCancelOrder" => self.handle_cancel_order(bytes).map(Answer::Response),
+            "privateCancelOrders" => self.handle_cancel_orders(bytes).map(Answer::Response),
+            // once is enough
+            "authenticate" if self.user.is_some() => Ok(Answer::Nothing),
+            "authenticate" => self.handle_authenticate(bytes).map(Answer::Event),
+            "subscribe" | "unsubscribe" => self.handle_subscription(bytes).map(Answer::Event),
+            // e.g. getMarkets, which the stub doesn't know about
+            action => {
+                log::info!("unknown action: {}", action);
+                reject(
+                    BitvavoErrorCode::InvalidEndpoint,
+                    "Invalid endpoint. Please check url and HTTP method.",
+                )
+            }
+        }
+    }
+
+    fn handle_get_book(&self, bytes: &[u8]) -> Result<serde_json::Value, Rejection> {
+        let request = serde_json::from_slice::<MarketRequest>(bytes)
+            .map_err(|_| invalid_parameters("market parameter is required."))?;
+        Ok(self
+            .exchange
+            .market_data
+            .lock()
+            .unwrap()
+            .book(&request.market))
+    }
+
+    fn handle_get_balance(&self) -> Result<serde_json::Value, Rejection> {
+        let Some(user) = self.user else {
+            return authentication_required();
+        };
+        Ok(json!(self.exchange.engine.lock().unwrap().balances(user)))
+    }
+
+    fn handle_create_order(&self, bytes: &[u8]) -> Result<serde_json::Value, Rejection> {
+        let Some(user) = self.user else {
+            return authentication_required();
+        };
+        let place_order =
+            serde_json::from_slice::<PlaceOrder>(bytes).map_err(invalid_parameters)?;
+        let placed = self
+            .exchange
+            .engine
+            .lock()
+            .unwrap()
+            .place(user, place_order, now());
+        self.publish(placed.map(|(order, notifications)| (json!(order), notifications)))
+    }
+
+    fn handle_cancel_order(&self, bytes: &[u8]) -> Result<serde_json::Value, Rejection> {
+        let Some(user) = self.user else {
+            return authentication_required();
+        };
+        let Some(order_id) = cancel_request(bytes).order_id else {
+            return reject(
+                BitvavoErrorCode::MissingOrIncompatibleParameters,
+                "orderId is missing.",
+            );
+        };
+        let cancelled = self
+            .exchange
+            .engine
+            .lock()
+            .unwrap()
+            .cancel(user, &order_id, now());
+        self.publish(cancelled.map(|(id, notifications)| (json!({ "orderId": id }), notifications)))
+    }
+
+    fn handle_cancel_orders(&self, bytes: &[u8]) -> Result<serde_json::Value, Rejection> {
+        let Some(user) = self.user else {
+            return authentication_required();
+        };
+        let market = cancel_request(bytes).market;
+        let (ids, notifications) =
+            self.exchange
+                .engine
+                .lock()
+                .unwrap()
+                .cancel_all(user, market.as_deref(), now());
+        let ids = ids
+            .into_iter()
+            .map(|id| json!({ "orderId": id }))
+            .collect::<Vec<_>>();
+        self.publish(Ok((json!(ids), notifications)))
+    }
+
+    // hands what an order operation changed to the subscribers
+    fn publish(
+        &self,
+        changed: Result<(serde_json::Value, Vec<Notification>), Rejection>,
+    ) -> Result<serde_json::Value, Rejection> {
+        let exchange = &self.exchange;
+        let response = changed.map(|(response, notifications)| {
+            let mut hub = exchange.hub.lock().unwrap();
+            notifications.into_iter().for_each(|n| hub.notify(n));
+            response
+        });
+        publish_market_data(&exchange.engine, &exchange.market_data, &exchange.hub);
+        response
+    }
+
+    // the connection stays open when it fails, like on the exchange
+    fn handle_authenticate(&mut self, bytes: &[u8]) -> Result<serde_json::Value, Rejection> {
+        let request =
+            serde_json::from_slice::<AuthenticateRequest>(bytes).map_err(invalid_parameters)?;
+        let authenticated = self
+            .exchange
+            .users
+            .read()
+            .unwrap()
+            .authenticate(&request, now());
+        let guid = authenticated
+            .inspect_err(|rejection| log::info!("authentication failed: {}", rejection.error))?;
+        self.exchange
+            .hub
+            .lock()
+            .unwrap()
+            .authenticate(self.id, guid);
+        self.user = Some(guid);
+        log::info!("authenticated user : {}", guid);
+        Ok(json!({ "event": "authenticate" }))
+    }
+
+    fn handle_subscription(&self, bytes: &[u8]) -> Result<serde_json::Value, Rejection> {
+        let subscription =
+            serde_json::from_slice::<SubscriptionRequest>(bytes).map_err(invalid_parameters)?;
+        let mut hub = self.exchange.hub.lock().unwrap();
+        for channel in subscription.channels {
+            log::info!(
+                "{}: {} {:?}",
+                subscription.action,
+                channel.name,
+                channel.markets
+            );
+            for key in channel.keys() {
+                for market in &channel.markets {
+                    match subscription.action.as_str() {
+                        "unsubscribe" => hub.unsubscribe(self.id, &key, market),
+                        _ => hub.subscribe(self.id, &key, market),
                     }
                 }
             }
-            log::info!("connection terminated :(");
-            websocket.close(None).unwrap();
+        }
+        let event = match subscription.action.as_str() {
+            "unsubscribe" => "unsubscribed",
+            _ => "subscribed",
+        };
+        Ok(json!({ "event": event, "subscriptions": hub.subscriptions(self.id) }))
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        self.exchange.hub.lock().unwrap().disconnect(self.id);
+        log::info!("connection terminated :(");
+        // the client might have gone already, nothing to close then
+        let closed = self.websocket.close(None);
+        if let Err(e) = closed.and(self.websocket.flush()) {
+            log::debug!("failed to close the connection: {}", e);
+        }
+    }
+}
+
+fn invalid_parameters(error: impl ToString) -> Rejection {
+    Rejection {
+        error_code: BitvavoErrorCode::MissingOrIncompatibleParameters,
+        error: error.to_string(),
+    }
+}
+
+// the cancel actions have no required parameters of their own
+fn cancel_request(bytes: &[u8]) -> CancelRequest {
+    serde_json::from_slice(bytes).unwrap_or(CancelRequest {
+        order_id: None,
+        market: None,
+    })
+}
+
+// stub_exchange [scenario.json], see scenario.rs and scenarios/ for the format;
+// it listens on STUB_EXCHANGE_ADDR, 127.0.0.1:9001 by default
+fn main() {
+    env_logger::init();
+    let scenario = match std::env::args().nth(1) {
+        Some(path) => Scenario::load(Path::new(&path)).unwrap_or_else(|e| panic!("{}", e)),
+        None => Scenario::default(),
+    };
+    let address =
+        std::env::var("STUB_EXCHANGE_ADDR").unwrap_or_else(|_| "127.0.0.1:9001".to_string());
+    let server = TcpListener::bind(address).unwrap();
+    let exchange = Exchange {
+        hub: Arc::default(),
+        engine: Arc::default(),
+        market_data: Arc::default(),
+        users: Arc::new(RwLock::new(UserStorage::new())),
+        scenario: Arc::new(scenario),
+    };
+
+    for guid in exchange.users.read().unwrap().guids() {
+        let mut engine = exchange.engine.lock().unwrap();
+        engine.deposit(guid, "BTC", "1.57593193".parse().unwrap());
+        engine.deposit(guid, "EUR", "214232.00".parse().unwrap());
+    }
+
+    for stream in server.incoming() {
+        let exchange = exchange.clone();
+
+        log::info!("spawning a new connection-thread");
+
+        spawn(move || {
+            let websocket = match stream {
+                Ok(stream) => accept(stream).map_err(|e| e.to_string()),
+                Err(e) => Err(e.to_string()),
+            };
+            let mut connection = match websocket {
+                Ok(websocket) => Connection::new(exchange, websocket),
+                Err(e) => {
+                    log::error!("failed to accept the connection: {}", e);
+                    return;
+                }
+            };
+            if let Err(e) = connection.serve() {
+                log::info!("connection failed: {}", e);
+            }
         });
     }
 }
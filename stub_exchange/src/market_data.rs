@@ -0,0 +1,373 @@
+use crate::engine::Level;
+use bitvavo_tungstenite::candle::{Candle, CandleEvent, Interval};
+use bitvavo_tungstenite::decimal::Decimal;
+use bitvavo_tungstenite::event::Ticker24h;
+use bitvavo_tungstenite::rug_float_serde::FloatWrapper;
+use bitvavo_tungstenite::trade::Trade;
+use serde_json::json;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+const DAY: u64 = 24 * 60 * 60 * 1000;
+
+// a public channel event for the subscribers of a market
+#[derive(Debug)]
+pub struct Publication {
+    // the channel as the hub knows it, see channel_key
+    pub channel: String,
+    pub market: String,
+    pub event: serde_json::Value,
+}
+
+// candles subscriptions are kept per interval
+pub fn channel_key(name: &str, interval: Option<&str>) -> String {
+    match interval {
+        Some(interval) => format!("{}_{}", name, interval),
+        None => name.to_string(),
+    }
+}
+
+type Levels = BTreeMap<Decimal, Decimal>;
+
+#[derive(Debug, Default)]
+struct MarketState {
+    nonce: u64,
+    // as last published
+    bids: Levels,
+    asks: Levels,
+    // the last 24 hours, oldest first
+    trades: VecDeque<Trade>,
+    // the current candle of every interval
+    candles: HashMap<Interval, Candle>,
+}
+
+impl MarketState {
+    fn best_bid(&self) -> Option<Level> {
+        self.bids.iter().next_back().map(|(p, q)| (*p, *q))
+    }
+
+    fn best_ask(&self) -> Option<Level> {
+        self.asks.iter().next().map(|(p, q)| (*p, *q))
+    }
+}
+
+// what the public channels of every market have shown so far: the book as of its
+// nonce, the trades of the last day and the running candles
+#[derive(Debug, Default)]
+pub struct MarketData {
+    markets: HashMap<String, MarketState>,
+}
+
+impl MarketData {
+    // publishes what changed since the last update of the market: the levels in
+    // a book event under the next nonce, then the trades, the candles they
+    // touched, the ticker if the top of the book moved and the 24h ticker
+    pub fn update(
+        &mut self,
+        market: &str,
+        bids: &[Level],
+        asks: &[Level],
+        trades: Vec<Trade>,
+        now: u64,
+    ) -> Vec<Publication> {
+        let state = self.markets.entry(market.to_string()).or_default();
+        let mut publications = Vec::new();
+        let publication = |channel: String, event| Publication {
+            channel,
+            market: market.to_string(),
+            event,
+        };
+
+        let top = (state.best_bid(), state.best_ask());
+        let bid_changes = changes(&mut state.bids, bids);
+        let ask_changes = changes(&mut state.asks, asks);
+        if !bid_changes.is_empty() || !ask_changes.is_empty() {
+            state.nonce += 1;
+            publications.push(publication(
+                channel_key("book", None),
+                json!({
+                    "event": "book",
+                    "market": market,
+                    "nonce": state.nonce,
+                    "bids": bid_changes,
+                    "asks": ask_changes,
+                }),
+            ));
+        }
+
+        let traded = !trades.is_empty();
+        for trade in trades {
+            let mut event = json!(trade);
+            event["event"] = json!("trade");
+            publications.push(publication(channel_key("trades", None), event));
+
+            for interval in Interval::ALL {
+                let start = interval.start_of(trade.timestamp);
+                let candle = state
+                    .candles
+                    .entry(interval)
+                    .and_modify(|candle| {
+                        if candle.timestamp == start {
+                            candle.high = candle.high.max(trade.price);
+                            candle.low = candle.low.min(trade.price);
+                            candle.close = trade.price;
+                            candle.volume += trade.amount;
+                        } else {
+                            *candle = open(start, &trade);
+                        }
+                    })
+                    .or_insert_with(|| open(start, &trade));
+                let mut event = json!(CandleEvent {
+                    market: market.to_string(),
+                    interval,
+                    candles: vec![*candle],
+                });
+                event["event"] = json!("candle");
+                publications.push(publication(
+                    channel_key("candles", Some(interval.as_str())),
+                    event,
+                ));
+            }
+            state.trades.push_back(trade);
+        }
+        while state
+            .trades
+            .front()
+            .is_some_and(|t| t.timestamp + DAY <= now)
+        {
+            state.trades.pop_front();
+        }
+
+        let top_moved = top != (state.best_bid(), state.best_ask());
+        if top_moved {
+            let (bid, ask) = (state.best_bid(), state.best_ask());
+            publications.push(publication(
+                channel_key("ticker", None),
+                json!({
+                    "event": "ticker",
+                    "market": market,
+                    "bestBid": bid.map(|(p, _)| p),
+                    "bestBidSize": bid.map(|(_, q)| q),
+                    "bestAsk": ask.map(|(p, _)| p),
+                    "bestAskSize": ask.map(|(_, q)| q),
+                }),
+            ));
+        }
+        if traded || top_moved {
+            publications.push(publication(
+                channel_key("ticker24h", None),
+                json!({
+                    "event": "ticker24h",
+                    "data": [ticker24h(market, state, now)],
+                }),
+            ));
+        }
+        publications
+    }
+
+    // the next book event comes a nonce later than it should, leaving a gap for
+    // the subscribers; snapshots carry on from there
+    pub fn skip_nonce(&mut self, market: &str) {
+        self.markets.entry(market.to_string()).or_default().nonce += 1;
+    }
+
+    // a getBook response, consistent with the book events sent so far
+    pub fn book(&self, market: &str) -> serde_json::Value {
+        let levels = |levels: Box<dyn Iterator<Item = (&Decimal, &Decimal)> + '_>| {
+            levels.map(|(p, q)| json!([p, q])).collect::<Vec<_>>()
+        };
+        match self.markets.get(market) {
+            Some(state) => json!({
+                "market": market,
+                "nonce": state.nonce,
+                "bids": levels(Box::new(state.bids.iter().rev())),
+                "asks": levels(Box::new(state.asks.iter())),
+            }),
+            None => json!({ "market": market, "nonce": 0, "bids": [], "asks": [] }),
+        }
+    }
+}
+
+// brings `published` up to `levels`, returning the [price, amount] pairs that
+// changed; a level that went away is sent with a zero amount
+fn changes(published: &mut Levels, levels: &[Level]) -> Vec<serde_json::Value> {
+    let current: Levels = levels.iter().copied().collect();
+    let mut changes = Vec::new();
+    for price in published.keys() {
+        if !current.contains_key(price) {
+            changes.push(json!([price, Decimal::ZERO]));
+        }
+    }
+    for (price, amount) in &current {
+        if published.get(price) != Some(amount) {
+            changes.push(json!([price, amount]));
+        }
+    }
+    *published = current;
+    changes
+}
+
+fn open(timestamp: u64, trade: &Trade) -> Candle {
+    Candle {
+        timestamp,
+        open: trade.price,
+        high: trade.price,
+        low: trade.price,
+        close: trade.price,
+        volume: trade.amount,
+    }
+}
+
+fn ticker24h(market: &str, state: &MarketState, now: u64) -> Ticker24h {
+    let price = |price: Option<Decimal>| price.map(FloatWrapper::from);
+    let trades = &state.trades;
+    let (bid, ask) = (state.best_bid(), state.best_ask());
+    Ticker24h {
+        market: market.to_string(),
+        open: price(trades.front().map(|t| t.price)),
+        high: price(trades.iter().map(|t| t.price).max()),
+        low: price(trades.iter().map(|t| t.price).min()),
+        last: price(trades.back().map(|t| t.price)),
+        volume: price(Some(trades.iter().map(|t| t.amount).sum())),
+        volume_quote: price(Some(trades.iter().map(|t| t.amount * t.price).sum())),
+        bid: price(bid.map(|(p, _)| p)),
+        bid_size: price(bid.map(|(_, q)| q)),
+        ask: price(ask.map(|(p, _)| p)),
+        ask_size: price(ask.map(|(_, q)| q)),
+        timestamp: now,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitvavo_tungstenite::decode::decode_event;
+    use bitvavo_tungstenite::event::BitvavoEvent;
+    use bitvavo_tungstenite::local_book::{BookSync, LocalBook};
+    use bitvavo_tungstenite::price_level::Book;
+    use bitvavo_tungstenite::side::Side;
+
+    // a Monday
+    const T0: u64 = 1_699_833_600_000;
+
+    fn d(s: &str) -> Decimal {
+        s.parse().unwrap()
+    }
+
+    fn level(price: &str, amount: &str) -> Level {
+        (d(price), d(amount))
+    }
+
+    #[test]
+    fn book_events_follow_the_snapshot() {
+        let mut market_data = MarketData::default();
+        market_data.update(
+            "BTC-EUR",
+            &[level("99", "1")],
+            &[level("101", "2")],
+            Vec::new(),
+            1,
+        );
+        let snapshot: Book = serde_json::from_value(market_data.book("BTC-EUR")).unwrap();
+        let mut local_book = LocalBook::default();
+        local_book.ingest_snapshot(snapshot);
+
+        let publications = market_data.update(
+            "BTC-EUR",
+            &[level("99", "1"), level("98", "3")],
+            &[],
+            Vec::new(),
+            2,
+        );
+        let channels: Vec<_> = publications.iter().map(|p| p.channel.as_str()).collect();
+        assert_eq!(channels, vec!["book", "ticker", "ticker24h"]);
+
+        let update: Book = serde_json::from_value(publications[0].event.clone()).unwrap();
+        assert_eq!(update.nonce, 2);
+        assert_eq!(local_book.ingest_book(update), BookSync::InSync);
+        assert_eq!(local_book.nonce(), Some(2));
+        assert!(local_book.top_ask().is_none());
+        assert_eq!(local_book.levels(Side::Buy).count(), 2);
+
+        // nothing changed, nothing to send
+        assert!(
+            market_data
+                .update(
+                    "BTC-EUR",
+                    &[level("99", "1"), level("98", "3")],
+                    &[],
+                    Vec::new(),
+                    3
+                )
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn trades_update_candles_and_tickers() {
+        let mut market_data = MarketData::default();
+        let trade = |offset: u64, amount: &str, price: &str| Trade {
+            market: "BTC-EUR".to_string(),
+            timestamp: T0 + offset,
+            id: offset.to_string(),
+            amount: d(amount),
+            price: d(price),
+            side: Side::Buy,
+        };
+        market_data.update(
+            "BTC-EUR",
+            &[],
+            &[],
+            vec![trade(60_000, "1", "100")],
+            T0 + 60_000,
+        );
+        let publications = market_data.update(
+            "BTC-EUR",
+            &[],
+            &[],
+            vec![trade(90_000, "2", "102")],
+            T0 + 90_000,
+        );
+
+        let decode = |channel: &str| {
+            let publication = publications.iter().find(|p| p.channel == channel).unwrap();
+            decode_event(&publication.event.to_string()).unwrap()
+        };
+        let BitvavoEvent::Trade(decoded) = decode("trades") else {
+            panic!("not a trade");
+        };
+        assert_eq!(decoded, trade(90_000, "2", "102"));
+        let BitvavoEvent::Candle(candle) = decode("candles_1m") else {
+            panic!("not a candle");
+        };
+        assert_eq!(candle.candles[0].open, d("100"));
+        assert_eq!(candle.candles[0].close, d("102"));
+        assert_eq!(candle.candles[0].volume, d("3"));
+        let BitvavoEvent::Ticker24h(tickers) = decode("ticker24h") else {
+            panic!("not a 24h ticker");
+        };
+        assert_eq!(tickers[0].volume_quote.as_ref().unwrap().str_repr, "304");
+
+        // a day after the first trade only the second is left
+        let publications = market_data.update(
+            "BTC-EUR",
+            &[level("98", "1")],
+            &[],
+            Vec::new(),
+            T0 + 60_000 + DAY,
+        );
+        let ticker = &publications.last().unwrap().event["data"][0];
+        assert_eq!(ticker["open"], "102");
+        assert_eq!(ticker["volume"], "2");
+
+        // a day later the trades are out of the 24h ticker
+        let publications = market_data.update(
+            "BTC-EUR",
+            &[level("99", "1")],
+            &[],
+            Vec::new(),
+            T0 + 90_000 + DAY,
+        );
+        let ticker = &publications.last().unwrap().event["data"][0];
+        assert_eq!(ticker["last"], serde_json::Value::Null);
+    }
+}
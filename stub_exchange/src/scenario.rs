@@ -0,0 +1,189 @@
+use crate::engine::Rejection;
+use bitvavo_tungstenite::error_code::BitvavoErrorCode;
+use serde::Deserialize;
+use std::collections::{HashMap, VecDeque};
+use std::path::Path;
+
+// a timeline of faults, replayed on every connection from the moment it opens,
+// e.g.
+//
+// {"steps": [
+//   {"at": 500, "action": "skipNonce", "market": "BTC-EUR"},
+//   {"at": 500, "action": "fail", "request": "privateCreateOrder", "errorCode": 216, "error": "..."},
+//   {"at": 2000, "action": "dropAfter", "messages": 3}
+// ]}
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Scenario {
+    pub steps: Vec<Step>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Step {
+    // milliseconds after the connection opened
+    #[serde(default)]
+    pub at: u64,
+    #[serde(flatten)]
+    pub fault: Fault,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "action", rename_all = "camelCase")]
+pub enum Fault {
+    // sent as they are
+    Send {
+        events: Vec<serde_json::Value>,
+    },
+    // the connection is closed once this many more messages went out
+    DropAfter {
+        messages: usize,
+    },
+    // held back before every response from now on, 0 to stop
+    Delay {
+        millis: u64,
+    },
+    // the next book event of the market leaves a nonce out
+    SkipNonce {
+        market: String,
+    },
+    // the next request with this action gets the error instead of a response
+    #[serde(rename_all = "camelCase")]
+    Fail {
+        request: String,
+        error_code: BitvavoErrorCode,
+        error: String,
+    },
+    // a text frame that isn't valid JSON
+    Malformed {
+        #[serde(default = "truncated_frame")]
+        frame: String,
+    },
+}
+
+fn truncated_frame() -> String {
+    r#"{"event":"book","market":"#.to_string()
+}
+
+impl Scenario {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| format!("cannot read {}: {}", path.display(), e))?;
+        serde_json::from_str(&contents).map_err(|e| format!("invalid scenario: {}", e))
+    }
+}
+
+// where a connection is in the scenario; the faults changing how it sends and
+// answers are kept here, the others are handed to the connection when due
+#[derive(Debug, Default)]
+pub struct Playback {
+    started: u64,
+    steps: VecDeque<Step>,
+    messages_left: Option<usize>,
+    delay: u64,
+    failures: HashMap<String, VecDeque<Rejection>>,
+}
+
+impl Playback {
+    pub fn new(scenario: &Scenario, now: u64) -> Self {
+        let mut steps = scenario.steps.clone();
+        // stable, so steps at the same time keep their order
+        steps.sort_by_key(|step| step.at);
+        Playback {
+            started: now,
+            steps: steps.into(),
+            ..Playback::default()
+        }
+    }
+
+    // the Send, SkipNonce and Malformed faults due by `now`, in order
+    pub fn due(&mut self, now: u64) -> Vec<Fault> {
+        let mut due = Vec::new();
+        while let Some(step) = self.steps.front()
+            && self.started + step.at <= now
+        {
+            match self.steps.pop_front().unwrap().fault {
+                Fault::DropAfter { messages } => self.messages_left = Some(messages),
+                Fault::Delay { millis } => self.delay = millis,
+                Fault::Fail {
+                    request,
+                    error_code,
+                    error,
+                } => self
+                    .failures
+                    .entry(request)
+                    .or_default()
+                    .push_back(Rejection { error_code, error }),
+                fault => due.push(fault),
+            }
+        }
+        due
+    }
+
+    pub fn take_failure(&mut self, action: &str) -> Option<Rejection> {
+        self.failures.get_mut(action)?.pop_front()
+    }
+
+    pub fn delay(&self) -> u64 {
+        self.delay
+    }
+
+    // counts a message about to go out, false if the connection should have
+    // been dropped instead
+    pub fn may_send(&mut self) -> bool {
+        match &mut self.messages_left {
+            Some(0) => false,
+            Some(left) => {
+                *left -= 1;
+                true
+            }
+            None => true,
+        }
+    }
+
+    pub fn should_drop(&self) -> bool {
+        self.messages_left == Some(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replay_a_scenario() {
+        let scenario: Scenario = serde_json::from_str(
+            r#"{"steps": [
+                {"at": 100, "action": "dropAfter", "messages": 2},
+                {"action": "fail", "request": "privateCreateOrder", "errorCode": 216, "error": "Insufficient balance."},
+                {"at": 50, "action": "skipNonce", "market": "BTC-EUR"},
+                {"at": 50, "action": "malformed"},
+                {"at": 50, "action": "delay", "millis": 250}
+            ]}"#,
+        )
+        .unwrap();
+        let mut playback = Playback::new(&scenario, 1_000);
+
+        assert!(playback.due(1_000).is_empty());
+        let failure = playback.take_failure("privateCreateOrder").unwrap();
+        assert_eq!(failure.error_code, BitvavoErrorCode::InsufficientBalance);
+        assert!(playback.take_failure("privateCreateOrder").is_none());
+
+        assert_eq!(
+            playback.due(1_060),
+            vec![
+                Fault::SkipNonce {
+                    market: "BTC-EUR".to_string()
+                },
+                Fault::Malformed {
+                    frame: truncated_frame()
+                }
+            ]
+        );
+        assert_eq!(playback.delay(), 250);
+
+        assert!(playback.may_send());
+        playback.due(1_100);
+        assert!(playback.may_send() && playback.may_send());
+        assert!(playback.should_drop());
+        assert!(!playback.may_send());
+    }
+}
@@ -0,0 +1,171 @@
+use bitvavo_tungstenite::bitvavo::SubscriptionBuilder;
+use bitvavo_tungstenite::connection::{
+    ConnectionEvent, ConnectionEventReceiver, ManagedConnectionBuilder,
+};
+use bitvavo_tungstenite::error_code::BitvavoErrorCode;
+use bitvavo_tungstenite::event::BitvavoEvent;
+use serde_json::json;
+use std::net::{TcpListener, TcpStream};
+use std::process::{Child, Command, Stdio};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+// the stub on a port of its own, killed with the test
+struct Stub {
+    process: Child,
+    url: String,
+}
+
+impl Stub {
+    fn start() -> Self {
+        let address = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let process = Command::new(env!("CARGO_BIN_EXE_stub_exchange"))
+            .env("STUB_EXCHANGE_ADDR", address.to_string())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap();
+        let started = Instant::now();
+        while TcpStream::connect(address).is_err() {
+            assert!(started.elapsed() < Duration::from_secs(5), "stub not up");
+            sleep(Duration::from_millis(10));
+        }
+        Stub {
+            process,
+            url: format!("ws://{}", address),
+        }
+    }
+}
+
+impl Drop for Stub {
+    fn drop(&mut self) {
+        _ = self.process.kill();
+        _ = self.process.wait();
+    }
+}
+
+// skips events until one matches
+async fn expect(events: &mut ConnectionEventReceiver, matches: fn(&ConnectionEvent) -> bool) {
+    let wait = async {
+        while let Some(event) = events.recv().await {
+            if matches(&event) {
+                return;
+            }
+        }
+        panic!("connection manager stopped");
+    };
+    tokio::time::timeout(Duration::from_secs(5), wait)
+        .await
+        .expect("no such event");
+}
+
+#[tokio::test]
+async fn reconnect_after_the_stub_drops_the_connection() {
+    let stub = Stub::start();
+    let (connection, mut events) = ManagedConnectionBuilder::default()
+        .with_url(stub.url.clone())
+        .with_credentials("xxx_yyyy".to_string(), "zzz_secret".to_string())
+        .with_backoff(Duration::from_millis(10), Duration::from_millis(10))
+        .connect();
+
+    // right away, before the connection is up
+    connection
+        .subscribe(
+            SubscriptionBuilder::default()
+                .with_market("BTC-EUR".to_string())
+                .with_ticker()
+                .build()
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    expect(&mut events, |e| {
+        matches!(e, ConnectionEvent::Event(Ok(BitvavoEvent::Authenticated)))
+    })
+    .await;
+    expect(&mut events, |e| {
+        matches!(e, ConnectionEvent::Event(Ok(BitvavoEvent::Subscribed)))
+    })
+    .await;
+
+    connection
+        .client()
+        .unwrap()
+        .send_action(json!({ "action": "dropConnection" }))
+        .await
+        .unwrap();
+    expect(&mut events, |e| matches!(e, ConnectionEvent::Disconnected)).await;
+    expect(&mut events, |e| matches!(e, ConnectionEvent::Connected)).await;
+    expect(&mut events, |e| {
+        matches!(e, ConnectionEvent::Event(Ok(BitvavoEvent::Authenticated)))
+    })
+    .await;
+    expect(&mut events, |e| {
+        matches!(e, ConnectionEvent::Event(Ok(BitvavoEvent::Subscribed)))
+    })
+    .await;
+    assert!(connection.client().is_some());
+}
+
+#[tokio::test]
+async fn bad_requests_keep_the_connection() {
+    let stub = Stub::start();
+    let (connection, mut events) = ManagedConnectionBuilder::default()
+        .with_url(stub.url.clone())
+        .connect();
+    expect(&mut events, |e| matches!(e, ConnectionEvent::Connected)).await;
+
+    let client = connection.client().unwrap();
+    client
+        .send_action(json!({ "action": "getMarkets", "requestId": 1 }))
+        .await
+        .unwrap();
+    expect(&mut events, |e| {
+        matches!(
+            e,
+            ConnectionEvent::Event(Ok(BitvavoEvent::Error {
+                error_code: BitvavoErrorCode::InvalidEndpoint,
+                ..
+            }))
+        )
+    })
+    .await;
+
+    // not an action at all, and a subscription without channels
+    for frame in [
+        json!("hello"),
+        json!({ "action": "subscribe", "channels": "ticker" }),
+    ] {
+        client.send_action(frame).await.unwrap();
+        expect(&mut events, |e| {
+            matches!(
+                e,
+                ConnectionEvent::Event(Ok(BitvavoEvent::Error {
+                    error_code: BitvavoErrorCode::MissingOrIncompatibleParameters,
+                    ..
+                }))
+            )
+        })
+        .await;
+    }
+
+    // still answered on the same connection
+    connection
+        .subscribe(
+            SubscriptionBuilder::default()
+                .with_market("BTC-EUR".to_string())
+                .with_ticker()
+                .build()
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    expect(&mut events, |e| {
+        assert!(!matches!(e, ConnectionEvent::Disconnected));
+        matches!(e, ConnectionEvent::Event(Ok(BitvavoEvent::Subscribed)))
+    })
+    .await;
+}